@@ -0,0 +1,8 @@
+-- Latest health report sent by each agent with its heartbeats
+
+ALTER TABLE agents ADD COLUMN IF NOT EXISTS health_status TEXT;
+ALTER TABLE agents ADD COLUMN IF NOT EXISTS dropped JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE agents ADD COLUMN IF NOT EXISTS health_reported_at TIMESTAMPTZ;
+
+COMMENT ON COLUMN agents.health_status IS 'Provider health from the last report: ok or degraded';
+COMMENT ON COLUMN agents.dropped IS 'Entries dropped on a full buffer since the agent started, per source';
@@ -24,7 +24,7 @@ use tracing_subscriber::FmtSubscriber;
 use crate::{
     auth::auth_middleware,
     db::Database,
-    models::{Claims, LogQuery, OtelLog},
+    models::{Claims, LogBatch, LogQuery, OtelLog},
     streaming::handle_websocket,
 };
 
@@ -151,7 +151,7 @@ async fn health_check() -> impl IntoResponse {
 async fn ingest_logs(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(logs): Json<Vec<OtelLog>>,
+    Json(batch): Json<LogBatch>,
 ) -> Result<StatusCode, AppError> {
     let token = headers
         .get("Authorization")
@@ -163,7 +163,7 @@ async fn ingest_logs(
         .await
         .map_err(|_| anyhow::anyhow!("Invalid or expired token"))?;
 
-    otel::ingest_logs(&state.db, logs, claims.service_id).await?;
+    otel::ingest_logs(&state.db, batch.into_logs(), claims.service_id).await?;
     Ok(StatusCode::ACCEPTED)
 }
 
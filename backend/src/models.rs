@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use sqlx::types::Json;
+use std::collections::HashMap;
 use uuid::Uuid;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,6 +21,7 @@ pub struct OtelLog {
     pub severity_number: Option<i32>,
     #[serde(rename = "serviceName")]
     pub service_name: String,
+    #[serde(deserialize_with = "body_text")]
     pub body: String,
     #[serde(rename = "resourceAttributes", skip_serializing_if = "Option::is_none")]
     pub resource_attributes: Option<JsonValue>,
@@ -34,6 +37,58 @@ pub struct OtelLog {
     pub service_id: Option<Uuid>,
 }
 
+/// Structured (map, list, number) bodies are stored as their JSON text
+fn body_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match JsonValue::deserialize(deserializer)? {
+        JsonValue::String(s) => s,
+        JsonValue::Null => String::new(),
+        other => other.to_string(),
+    })
+}
+
+/// Log batch as sent by agents: an envelope whose resource attributes apply to
+/// every log, or a bare array from agents that predate resource attributes
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum LogBatch {
+    Envelope {
+        #[serde(rename = "resourceAttributes", default)]
+        resource_attributes: Option<JsonValue>,
+        logs: Vec<OtelLog>,
+    },
+    Logs(Vec<OtelLog>),
+}
+
+impl LogBatch {
+    /// Logs with the batch resource attributes merged in; keys already set on a log win
+    pub fn into_logs(self) -> Vec<OtelLog> {
+        let (resource, mut logs) = match self {
+            LogBatch::Envelope { resource_attributes: Some(JsonValue::Object(resource)), logs } => (resource, logs),
+            LogBatch::Envelope { logs, .. } | LogBatch::Logs(logs) => return logs,
+        };
+
+        for log in &mut logs {
+            let mut merged = resource.clone();
+            if let Some(JsonValue::Object(own)) = log.resource_attributes.take() {
+                merged.extend(own);
+            }
+            log.resource_attributes = Some(JsonValue::Object(merged));
+        }
+        logs
+    }
+}
+
+/// Provider health an agent sends with its heartbeats
+#[derive(Debug, Deserialize)]
+pub struct HealthReport {
+    pub status: String,
+    #[serde(default)]
+    pub components: HashMap<String, JsonValue>,
+    /// Entries dropped on a full buffer since the agent started, per source
+    #[serde(default)]
+    pub dropped: HashMap<String, u64>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogQuery {
     pub service: Option<String>,
@@ -90,6 +145,9 @@ pub struct Agent {
     pub expires_at: Option<DateTime<Utc>>,
     pub last_used_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    pub health_status: Option<String>,
+    pub dropped: Json<HashMap<String, u64>>,
+    pub health_reported_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -115,4 +173,7 @@ pub struct AgentResponse {
     pub expires_at: Option<DateTime<Utc>>,
     pub last_used_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    pub health_status: Option<String>,
+    pub dropped: Json<HashMap<String, u64>>,
+    pub health_reported_at: Option<DateTime<Utc>>,
 }
@@ -272,7 +272,8 @@ pub async fn create_agent(
         r#"
         INSERT INTO agents (service_id, name, token, source_type, expires_at)
         VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, service_id, name, token, source_type, expires_at, last_used_at, created_at
+        RETURNING id, service_id, name, token, source_type, expires_at, last_used_at, created_at,
+                  health_status, dropped, health_reported_at
         "#,
     )
     .bind(service_id)
@@ -310,7 +311,8 @@ pub async fn list_agents(
 
     let agents: Vec<Agent> = sqlx::query_as(
         r#"
-        SELECT id, service_id, name, token, source_type, expires_at, last_used_at, created_at
+        SELECT id, service_id, name, token, source_type, expires_at, last_used_at, created_at,
+               health_status, dropped, health_reported_at
         FROM agents
         WHERE service_id = $1
         ORDER BY created_at DESC
@@ -332,6 +334,9 @@ pub async fn list_agents(
             expires_at: agent.expires_at,
             last_used_at: agent.last_used_at,
             created_at: agent.created_at,
+            health_status: agent.health_status,
+            dropped: agent.dropped,
+            health_reported_at: agent.health_reported_at,
         })
         .collect();
 
@@ -377,7 +382,8 @@ pub async fn validate_agent_token(
 ) -> Result<AgentClaims, StatusCode> {
     let result: Agent = sqlx::query_as(
         r#"
-        SELECT id, service_id, name, token, source_type, expires_at, last_used_at, created_at
+        SELECT id, service_id, name, token, source_type, expires_at, last_used_at, created_at,
+               health_status, dropped, health_reported_at
         FROM agents
         WHERE token = $1
           AND (expires_at IS NULL OR expires_at > NOW())
@@ -9,7 +9,7 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
 use tracing::{error, info, warn};
 
-use crate::{db::Database, models::OtelLog, otel};
+use crate::{db::Database, models::{HealthReport, LogBatch, OtelLog}, otel};
 
 const MAGIC_BYTES: &[u8; 4] = b"ILOG";
 const VERSION: u8 = 1;
@@ -183,6 +183,12 @@ async fn handle_client(mut stream: TcpStream, db: Arc<Database>, log_tx: broadca
                     }
                     FrameType::Heartbeat => {
                         info!("Received heartbeat from {:?}", peer_addr);
+                        // Agents that report health send it encrypted like a batch
+                        if !frame.payload.is_empty() {
+                            if let Err(e) = process_health_report(&frame.payload, &db).await {
+                                warn!("Ignoring health report from {:?}: {}", peer_addr, e);
+                            }
+                        }
                         if let Err(e) = Frame::ack().write_to(&mut stream).await {
                             error!("Failed to send heartbeat ACK: {}", e);
                             break;
@@ -209,11 +215,9 @@ async fn handle_client(mut stream: TcpStream, db: Arc<Database>, log_tx: broadca
     info!("✗ Connection closed for agent: {:?}", peer_addr);
 }
 
-async fn process_log_batch(
-    encrypted_payload: &[u8],
-    db: &Database,
-    log_tx: &broadcast::Sender<OtelLog>,
-) -> Result<(uuid::Uuid, usize)> {
+/// Decrypt and decompress a frame payload with the first agent token that
+/// fits, returning the JSON bytes with the authenticated service and agent ids
+async fn open_payload(encrypted_payload: &[u8], db: &Database) -> Result<(uuid::Uuid, uuid::Uuid, Vec<u8>)> {
     // Fetch all active agent tokens from database
     let agents: Vec<(uuid::Uuid, uuid::Uuid, String)> = sqlx::query_as(
         r#"
@@ -256,11 +260,60 @@ async fn process_log_batch(
 
     // Decompress (agent uses raw block compression without size prefix)
     let json_bytes = lz4_flex::block::decompress(&compressed, 10 * 1024 * 1024)
-        .context("Failed to decompress log batch")?;
+        .context("Failed to decompress payload")?;
+
+    Ok((service_id, agent_id, json_bytes))
+}
+
+fn parse_health_report(json_bytes: &[u8]) -> Result<HealthReport> {
+    serde_json::from_slice(json_bytes).context("Failed to deserialize health report")
+}
+
+/// Store the provider health an agent reports with its heartbeats on its row
+async fn process_health_report(encrypted_payload: &[u8], db: &Database) -> Result<()> {
+    let (service_id, agent_id, json_bytes) = open_payload(encrypted_payload, db).await?;
+    let report = parse_health_report(&json_bytes)?;
+
+    sqlx::query(
+        "UPDATE agents SET health_status = $2, dropped = $3, health_reported_at = NOW() WHERE id = $1",
+    )
+    .bind(agent_id)
+    .bind(&report.status)
+    .bind(sqlx::types::Json(&report.dropped))
+    .execute(db.pool())
+    .await
+    .context("Failed to store health report")?;
+
+    // Totals since the agent started, per source
+    if !report.dropped.is_empty() {
+        warn!("Agent for service {} dropped entries on a full buffer: {:?}", service_id, report.dropped);
+    }
+    if report.status == "ok" {
+        info!("Agent for service {} reports healthy providers", service_id);
+        return Ok(());
+    }
+    for (name, component) in &report.components {
+        if component["state"] == "backoff" {
+            warn!(
+                "Agent for service {}: {} is restarting after {} failures (last error: {})",
+                service_id, name, component["restarts"], component["last_error"].as_str().unwrap_or("unknown")
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn process_log_batch(
+    encrypted_payload: &[u8],
+    db: &Database,
+    log_tx: &broadcast::Sender<OtelLog>,
+) -> Result<(uuid::Uuid, usize)> {
+    let (service_id, _, json_bytes) = open_payload(encrypted_payload, db).await?;
 
     // Deserialize
-    let mut logs: Vec<OtelLog> =
+    let batch: LogBatch =
         serde_json::from_slice(&json_bytes).context("Failed to deserialize logs")?;
+    let mut logs = batch.into_logs();
 
     let count = logs.len();
 
@@ -304,3 +357,24 @@ pub async fn start_tcp_server(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_report_dropped_counts() {
+        // As sent by the agent's `Health::snapshot`
+        let report = parse_health_report(
+            br#"{"status":"degraded","components":{"file:/var/log/app.log":{"state":"backoff","restarts":3}},"dropped":{"file":12,"docker":3}}"#,
+        )
+        .unwrap();
+        assert_eq!(report.status, "degraded");
+        assert_eq!(report.dropped["file"], 12);
+        assert_eq!(report.dropped["docker"], 3);
+        assert_eq!(report.components["file:/var/log/app.log"]["restarts"], 3);
+
+        let report = parse_health_report(br#"{"status":"ok","components":{}}"#).unwrap();
+        assert!(report.dropped.is_empty());
+    }
+}
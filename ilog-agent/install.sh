@@ -75,6 +75,7 @@ Wants=network-online.target
 Type=simple
 User=root
 ExecStart=/usr/local/bin/ilog-agent --config /etc/ilog/config.toml
+ExecReload=/bin/kill -HUP $MAINPID
 Restart=always
 RestartSec=5
 StandardOutput=journal
@@ -86,6 +87,7 @@ PrivateTmp=true
 ProtectSystem=strict
 ProtectHome=true
 ReadWritePaths=/var/log
+StateDirectory=ilog-agent
 
 # Resource limits
 LimitNOFILE=65536
@@ -0,0 +1,501 @@
+use anyhow::{Context, Result};
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, Notify};
+use tracing::{error, info, warn};
+
+use crate::config::{AgentConfig, BufferConfig, OverflowPolicy};
+use crate::health::Health;
+use crate::providers;
+use crate::tcp_sender::LogEntry;
+
+/// Entries handed from the buffer to the processor chain at a time
+const OUTPUT_CHANNEL: usize = 16;
+
+/// Most bytes read back from the spill file at once
+const SPILL_READ_BYTES: usize = 1024 * 1024;
+
+/// Source recorded for entries read back from the spill file
+const SPILLED: &str = "spill";
+
+/// Rough in-memory size of an entry, counted against the memory limit
+fn approx_size(entry: &LogEntry) -> usize {
+    fn map_size(map: &serde_json::Map<String, serde_json::Value>) -> usize {
+        map.iter().map(|(key, value)| key.len() + 24 + value_size(value)).sum::<usize>() + 48
+    }
+    fn value_size(value: &serde_json::Value) -> usize {
+        match value {
+            serde_json::Value::String(s) => s.len() + 24,
+            serde_json::Value::Array(items) => items.iter().map(value_size).sum::<usize>() + 24,
+            serde_json::Value::Object(map) => map_size(map),
+            _ => 16,
+        }
+    }
+    std::mem::size_of::<LogEntry>()
+        + entry.service.len()
+        + entry.message.len()
+        + entry.body.as_ref().map_or(0, value_size)
+        + entry.attributes.as_ref().map_or(0, value_size)
+        + entry.resource.as_ref().map_or(0, map_size)
+}
+
+/// Overflow policy of every source, rejecting names that are not a source of this build
+pub fn policies(config: &BufferConfig) -> Result<HashMap<&'static str, OverflowPolicy>> {
+    let names: Vec<&'static str> = providers::sources().iter().map(|source| source.name).collect();
+    for name in config.sources.keys() {
+        if !names.contains(&name.as_str()) {
+            anyhow::bail!("buffer.sources.{}: unknown source (available: {})", name, names.join(", "));
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| (name, config.sources.get(name).copied().unwrap_or(config.overflow)))
+        .collect())
+}
+
+/// Entries waiting on disk: appended at the end, read from `read_offset`
+struct Spill {
+    path: PathBuf,
+    file: File,
+    read_offset: u64,
+    len: u64,
+    limit: u64,
+}
+
+impl Spill {
+    fn open(dir: &Path, limit: u64) -> Result<Self> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create spill directory {}", dir.display()))?;
+        let path = dir.join("buffer.ndjson");
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open spill file {}", path.display()))?;
+        let len = file.metadata()?.len();
+        if len > 0 {
+            info!("Sending {} bytes of entries spilled before the last shutdown", len);
+        }
+        Ok(Self { path, file, read_offset: 0, len, limit })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.read_offset >= self.len
+    }
+
+    /// Append `entry`; false if the spill is full
+    fn push(&mut self, entry: &LogEntry) -> Result<bool> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        if self.len - self.read_offset + line.len() as u64 > self.limit {
+            return Ok(false);
+        }
+        self.file.write_all(&line).context("Failed to write spill file")?;
+        self.len += line.len() as u64;
+        Ok(true)
+    }
+
+    /// Read up to `max_bytes` of entries, emptying the file once all are read
+    fn read(&mut self, max_bytes: usize) -> Result<Vec<LogEntry>> {
+        self.file.seek(SeekFrom::Start(self.read_offset))?;
+        let mut reader = BufReader::new((&self.file).take(self.len - self.read_offset));
+        let mut entries = Vec::new();
+        let mut consumed = 0;
+        let mut line = Vec::new();
+
+        while consumed < max_bytes {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            consumed += read;
+            match serde_json::from_slice(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping unreadable entry in {}: {}", self.path.display(), e),
+            }
+        }
+
+        self.read_offset += consumed as u64;
+        if self.is_empty() {
+            self.file.set_len(0)?;
+            self.read_offset = 0;
+            self.len = 0;
+        }
+        Ok(entries)
+    }
+
+    /// Drop the part that was already read, so a restart does not send it again
+    fn compact(&mut self) -> Result<()> {
+        if self.read_offset == 0 {
+            return Ok(());
+        }
+        let tmp = self.path.with_extension("ndjson.tmp");
+        self.file.seek(SeekFrom::Start(self.read_offset))?;
+        std::io::copy(&mut (&self.file).take(self.len - self.read_offset), &mut File::create(&tmp)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        self.file = OpenOptions::new().read(true).append(true).open(&self.path)?;
+        self.len -= self.read_offset;
+        self.read_offset = 0;
+        Ok(())
+    }
+}
+
+/// Run file I/O on `slot`'s spill on the blocking pool; `None` if spilling is
+/// disabled. The spill is lost if the blocking task panics.
+async fn with_spill<T: Send + 'static>(
+    slot: &mut Option<Spill>,
+    f: impl FnOnce(&mut Spill) -> Result<T> + Send + 'static,
+) -> Option<Result<T>> {
+    let mut spill = slot.take()?;
+    match tokio::task::spawn_blocking(move || {
+        let result = f(&mut spill);
+        (spill, result)
+    })
+    .await
+    {
+        Ok((spill, result)) => {
+            *slot = Some(spill);
+            Some(result)
+        }
+        Err(e) => Some(Err(anyhow::anyhow!("Spill file task failed: {}", e))),
+    }
+}
+
+struct Queued {
+    source: &'static str,
+    entry: LogEntry,
+    size: usize,
+}
+
+struct Inner {
+    queue: VecDeque<Queued>,
+    used: usize,
+    closed: bool,
+}
+
+impl Inner {
+    fn fits(&self, size: usize, limit: usize) -> bool {
+        // An entry larger than the whole limit still gets through an empty queue
+        self.queue.is_empty() || self.used + size <= limit
+    }
+
+    /// Remove `source`'s oldest entries until `size` fits; false if that is not enough
+    fn evict_oldest(&mut self, source: &str, size: usize, limit: usize, health: &Health) -> bool {
+        while !self.fits(size, limit) {
+            let Some(index) = self.queue.iter().position(|queued| queued.source == source) else {
+                return false;
+            };
+            let evicted = self.queue.remove(index).unwrap();
+            self.used -= evicted.size;
+            report_drop(health, source, OverflowPolicy::DropOldest);
+        }
+        true
+    }
+}
+
+fn report_drop(health: &Health, source: &str, policy: OverflowPolicy) {
+    let count = health.add_dropped(source);
+    if count == 1 || count.is_multiple_of(1000) {
+        warn!("Buffer full: {} entries from {} dropped so far ({:?})", count, source, policy);
+    }
+}
+
+/// Queue between the providers and the processor chain, bounded in bytes,
+/// applying each source's overflow policy when it is full
+pub struct Buffer {
+    limit: usize,
+    policies: HashMap<&'static str, OverflowPolicy>,
+    inner: Mutex<Inner>,
+    /// Taken before `inner`, never while holding it
+    spill: tokio::sync::Mutex<Option<Spill>>,
+    /// Wakes producers waiting for space
+    space: Notify,
+    /// Wakes the consumer when entries arrive or the buffer closes
+    ready: Notify,
+    health: Arc<Health>,
+}
+
+impl Buffer {
+    pub fn new(config: &AgentConfig, health: Arc<Health>) -> Result<Self> {
+        let buffer = &config.buffer;
+        let policies = policies(buffer)?;
+
+        let spill = if policies.values().any(|policy| *policy == OverflowPolicy::Spill) {
+            let dir = buffer.spill_dir.clone().unwrap_or_else(|| config.agent.state_dir.join("spill"));
+            match Spill::open(&dir, buffer.spill_limit_bytes) {
+                Ok(spill) => Some(spill),
+                Err(e) => {
+                    error!("Spilling disabled, entries are dropped instead: {:#}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            limit: buffer.memory_limit_bytes,
+            policies,
+            inner: Mutex::new(Inner {
+                queue: VecDeque::new(),
+                used: 0,
+                closed: false,
+            }),
+            spill: tokio::sync::Mutex::new(spill),
+            space: Notify::new(),
+            ready: Notify::new(),
+            health,
+        })
+    }
+
+    /// Create the buffer and a task feeding its entries into the returned channel
+    pub fn start(config: &AgentConfig, health: Arc<Health>) -> Result<(Arc<Self>, mpsc::Receiver<LogEntry>)> {
+        let buffer = Arc::new(Self::new(config, health)?);
+        let (tx, rx) = mpsc::channel(OUTPUT_CHANNEL);
+        let output = buffer.clone();
+        tokio::spawn(async move {
+            while let Some(entry) = output.pop().await {
+                if tx.send(entry).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok((buffer, rx))
+    }
+
+    /// Queue an entry from `source`, waiting for space under the `block`
+    /// policy; false once the buffer is closed
+    pub async fn push(&self, source: &'static str, entry: LogEntry) -> bool {
+        let policy = self.policies.get(source).copied().unwrap_or_default();
+        let size = approx_size(&entry);
+
+        loop {
+            let space = self.space.notified();
+            tokio::pin!(space);
+            space.as_mut().enable();
+
+            // Spilling sources keep their order: they choose between memory and
+            // disk under the spill lock, and once entries are on disk new ones follow them
+            let mut spill = match policy {
+                OverflowPolicy::Spill => Some(self.spill.lock().await),
+                _ => None,
+            };
+            let spilling = spill.as_ref().is_some_and(|spill| spill.as_ref().is_some_and(|spill| !spill.is_empty()));
+
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if inner.closed {
+                    return false;
+                }
+
+                if !spilling && inner.fits(size, self.limit) {
+                    inner.used += size;
+                    inner.queue.push_back(Queued { source, entry, size });
+                    self.ready.notify_one();
+                    return true;
+                }
+
+                match policy {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropNewest => {
+                        report_drop(&self.health, source, policy);
+                        return true;
+                    }
+                    OverflowPolicy::DropOldest => {
+                        if inner.evict_oldest(source, size, self.limit, &self.health) {
+                            inner.used += size;
+                            inner.queue.push_back(Queued { source, entry, size });
+                            self.ready.notify_one();
+                        } else {
+                            report_drop(&self.health, source, policy);
+                        }
+                        return true;
+                    }
+                    // Written below, without holding the memory queue
+                    OverflowPolicy::Spill => {}
+                }
+            }
+
+            if let Some(spill) = spill.as_mut() {
+                let spilled = match with_spill(spill, move |spill| spill.push(&entry)).await {
+                    Some(Ok(spilled)) => spilled,
+                    Some(Err(e)) => {
+                        error!("{:#}", e);
+                        false
+                    }
+                    None => false,
+                };
+                if spilled {
+                    self.ready.notify_one();
+                } else {
+                    report_drop(&self.health, source, policy);
+                }
+                return true;
+            }
+
+            space.await;
+        }
+    }
+
+    /// Next entry, from memory first and then from the spill file. After
+    /// `close` only what is in memory is returned; the spill file is kept
+    /// for the next start.
+    pub async fn pop(&self) -> Option<LogEntry> {
+        loop {
+            let ready = self.ready.notified();
+            tokio::pin!(ready);
+            ready.as_mut().enable();
+
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(queued) = inner.queue.pop_front() {
+                    inner.used -= queued.size;
+                    self.space.notify_waiters();
+                    return Some(queued.entry);
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+
+            // Memory is empty, refill it from disk
+            let mut spill = self.spill.lock().await;
+            if spill.as_ref().is_some_and(|spill| !spill.is_empty()) {
+                let max_bytes = SPILL_READ_BYTES.min(self.limit);
+                match with_spill(&mut spill, move |spill| spill.read(max_bytes)).await {
+                    Some(Ok(entries)) => {
+                        let mut inner = self.inner.lock().unwrap();
+                        for entry in entries {
+                            let size = approx_size(&entry);
+                            inner.used += size;
+                            inner.queue.push_back(Queued { source: SPILLED, entry, size });
+                        }
+                        continue;
+                    }
+                    Some(Err(e)) => {
+                        error!("Spilled entries are lost, the spill file cannot be read: {:#}", e);
+                        *spill = None;
+                    }
+                    None => {}
+                }
+            }
+            drop(spill);
+
+            ready.await;
+        }
+    }
+
+    /// Stop accepting entries; the consumer gets what is left in memory,
+    /// then `None`
+    pub async fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.space.notify_waiters();
+        self.ready.notify_one();
+
+        if let Some(Err(e)) = with_spill(&mut *self.spill.lock().await, Spill::compact).await {
+            warn!("Failed to compact the spill file: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    const SOURCE: &str = "file";
+
+    fn new_buffer(overflow: OverflowPolicy, spill_dir: Option<PathBuf>) -> (Buffer, Arc<Health>) {
+        let mut config: AgentConfig = toml::from_str("[agent]\nserver = \"127.0.0.1:1\"\ntoken = \"t\"").unwrap();
+        config.buffer.overflow = overflow;
+        config.buffer.memory_limit_bytes = 3 * approx_size(&entry(0));
+        config.buffer.spill_dir = spill_dir;
+        let health = Arc::new(Health::default());
+        (Buffer::new(&config, health.clone()).unwrap(), health)
+    }
+
+    fn entry(i: usize) -> LogEntry {
+        test_entry(&format!("entry {:03}", i), json!({}))
+    }
+
+    async fn messages(buffer: &Buffer) -> Vec<String> {
+        buffer.close().await;
+        let mut messages = Vec::new();
+        while let Some(entry) = buffer.pop().await {
+            messages.push(entry.message);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn test_drop_policies() {
+        let (buffer, health) = new_buffer(OverflowPolicy::DropNewest, None);
+        for i in 0..5 {
+            assert!(buffer.push(SOURCE, entry(i)).await);
+        }
+        assert_eq!(messages(&buffer).await, ["entry 000", "entry 001", "entry 002"]);
+        assert_eq!(health.snapshot()["dropped"][SOURCE], 2);
+
+        let (buffer, health) = new_buffer(OverflowPolicy::DropOldest, None);
+        for i in 0..5 {
+            buffer.push(SOURCE, entry(i)).await;
+        }
+        assert_eq!(messages(&buffer).await, ["entry 002", "entry 003", "entry 004"]);
+        assert_eq!(health.snapshot()["dropped"][SOURCE], 2);
+    }
+
+    #[tokio::test]
+    async fn test_block_waits_for_space() {
+        let (buffer, _) = new_buffer(OverflowPolicy::Block, None);
+        let buffer = Arc::new(buffer);
+        for i in 0..3 {
+            buffer.push(SOURCE, entry(i)).await;
+        }
+
+        let producer = tokio::spawn({
+            let buffer = buffer.clone();
+            async move { buffer.push(SOURCE, entry(3)).await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!producer.is_finished());
+
+        assert_eq!(buffer.pop().await.unwrap().message, "entry 000");
+        assert!(producer.await.unwrap());
+        assert_eq!(messages(&buffer).await, ["entry 001", "entry 002", "entry 003"]);
+    }
+
+    #[tokio::test]
+    async fn test_spill_keeps_order_and_survives_restart() {
+        let dir = std::env::temp_dir().join(format!("ilog-spill-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let (spilling, health) = new_buffer(OverflowPolicy::Spill, Some(dir.clone()));
+        for i in 0..6 {
+            spilling.push(SOURCE, entry(i)).await;
+        }
+        for i in 0..3 {
+            assert_eq!(spilling.pop().await.unwrap().message, format!("entry {:03}", i));
+        }
+        // There is room in memory again, but this one queues behind what is on disk
+        spilling.push(SOURCE, entry(6)).await;
+        assert_eq!(messages(&spilling).await, Vec::<String>::new());
+        assert_eq!(health.snapshot()["dropped"], json!({}));
+        drop(spilling);
+
+        // What was still spilled at shutdown is sent after a restart
+        let (restarted, _) = new_buffer(OverflowPolicy::Spill, Some(dir.clone()));
+        let mut rest = Vec::new();
+        for _ in 0..4 {
+            rest.push(restarted.pop().await.unwrap().message);
+        }
+        assert_eq!(rest, ["entry 003", "entry 004", "entry 005", "entry 006"]);
+        assert_eq!(std::fs::metadata(dir.join("buffer.ndjson")).unwrap().len(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+use crate::config::AgentConfig;
+use crate::processors::ProcessorChain;
+use crate::protocol::{Frame, FrameType};
+use crate::providers;
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+use crate::severity::SeverityMapper;
+use crate::tcp_sender::{Connection, LogEntry, TcpLogSender};
+
+/// How long `test-connection` waits for each step
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shown instead of secrets in the printed configuration
+const HIDDEN: &str = "********";
+
+fn hide_secrets(config: &AgentConfig) -> AgentConfig {
+    let mut config = config.clone();
+    config.agent.token = HIDDEN.to_string();
+    for output in config.outputs.values_mut() {
+        output.token = HIDDEN.to_string();
+    }
+    #[cfg(feature = "http-server")]
+    if let Some(http) = config.sources.http.as_mut() {
+        if http.token.is_some() {
+            http.token = Some(HIDDEN.to_string());
+        }
+    }
+    for processor in &mut config.processors {
+        if let crate::config::ProcessorConfig::Redact(redact) = processor {
+            if redact.hash_salt.is_some() {
+                redact.hash_salt = Some(HIDDEN.to_string());
+            }
+        }
+    }
+    config
+}
+
+/// `check-config`: validate everything that can be checked without running,
+/// then print the effective configuration (defaults and environment overrides applied)
+pub async fn check_config(path: &Path) -> Result<()> {
+    let config = AgentConfig::load(path).with_context(|| format!("Invalid configuration {}", path.display()))?;
+    let mut problems = 0;
+
+    let mut report = |what: &str, result: Result<Vec<String>>| match result {
+        Ok(notes) => {
+            println!("✓ {}", what);
+            for note in notes {
+                println!("    {}", note);
+            }
+        }
+        Err(e) => {
+            println!("✗ {}: {:#}", what, e);
+            problems += 1;
+        }
+    };
+
+    #[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+    report("severity", SeverityMapper::from_config(&config.severity).map(|_| Vec::new()));
+    report(
+        "processors",
+        ProcessorChain::from_config(&config.processors)
+            .and_then(|chain| chain.with_trace_context(&config.trace_context))
+            .map(|_| vec![format!("{} stages", config.processors.len())]),
+    );
+
+    report(
+        "buffer",
+        crate::buffer::policies(&config.buffer)
+            .map(|_| vec![format!("{} bytes in memory, {:?} by default", config.buffer.memory_limit_bytes, config.buffer.overflow)]),
+    );
+
+    let targets = crate::output::targets(&config);
+    report(
+        "routes",
+        crate::output::Router::from_config(&config, &targets)
+            .map(|_| vec![format!("{} outputs, {} routes", targets.len(), config.routes.len())]),
+    );
+
+    let config = Arc::new(config);
+    for source in providers::sources() {
+        if source.enabled(&config) {
+            let provider = source.build(config.clone(), Arc::default());
+            report(&format!("sources.{}", source.name), provider.check().await);
+        }
+    }
+
+    println!();
+    println!("# Effective configuration (secrets hidden)");
+    println!("{}", toml::to_string_pretty(&hide_secrets(&config)).context("Failed to print configuration")?);
+
+    if problems > 0 {
+        anyhow::bail!("{} problem(s) found in {}", problems, path.display());
+    }
+    Ok(())
+}
+
+/// Next frame from the server, with a deadline
+async fn read_frame(stream: &mut TcpStream) -> Result<Frame> {
+    tokio::time::timeout(STEP_TIMEOUT, Frame::read_from(stream))
+        .await
+        .context("Timed out waiting for the server")?
+}
+
+/// `test-connection`: for each server, connect and send a heartbeat, then an
+/// empty authenticated batch per output sending there
+pub async fn test_connection(path: &Path) -> Result<()> {
+    let config = AgentConfig::load(path).with_context(|| format!("Invalid configuration {}", path.display()))?;
+    if config.agent.protocol != "tcp" {
+        anyhow::bail!("test-connection supports the tcp protocol only, not '{}'", config.agent.protocol);
+    }
+    let targets = crate::output::targets(&config);
+    let mut servers: Vec<&str> = Vec::new();
+    for target in &targets {
+        if !servers.contains(&target.server.as_str()) {
+            servers.push(&target.server);
+        }
+    }
+
+    for server in servers {
+        let started = Instant::now();
+        let mut stream = tokio::time::timeout(STEP_TIMEOUT, TcpStream::connect(server))
+            .await
+            .with_context(|| format!("Timed out connecting to {}", server))?
+            .with_context(|| format!("Failed to connect to {}", server))?;
+        println!("✓ Connected to {} ({}) in {:?}", server, stream.peer_addr()?, started.elapsed());
+
+        let started = Instant::now();
+        Frame::heartbeat().write_to(&mut stream).await?;
+        match read_frame(&mut stream).await?.frame_type {
+            FrameType::Ack => println!("✓ Heartbeat acknowledged in {:?}", started.elapsed()),
+            other => anyhow::bail!("Unexpected {:?} frame in reply to a heartbeat", other),
+        }
+
+        // The server only acknowledges batches it could decrypt with a known agent token
+        for target in targets.iter().filter(|target| target.server == server) {
+            let sender = TcpLogSender::new(&config, target, Connection::default())?;
+            let started = Instant::now();
+            Frame::log_batch(sender.encode_batch(&[])?).write_to(&mut stream).await?;
+            match read_frame(&mut stream).await {
+                Ok(frame) if matches!(frame.frame_type, FrameType::Ack) => {
+                    println!("✓ Token of output {} accepted in {:?}", target.name, started.elapsed())
+                }
+                Ok(frame) => anyhow::bail!("Unexpected {:?} frame in reply to a log batch", frame.frame_type),
+                Err(e) => anyhow::bail!("Token rejected for output {}: the server closed the connection ({:#})", target.name, e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `run --dry-run`: print entries as they would be sent, one JSON object per line
+pub async fn print_entries(mut rx: mpsc::Receiver<LogEntry>) -> Result<()> {
+    while let Some(entry) = rx.recv().await {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", entry.to_otlp_json())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Encryptor;
+    use tokio::net::TcpListener;
+
+    /// Minimal backend: acknowledges heartbeats, and batches it can decrypt with `token`
+    async fn fake_server(token: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let decryptor = Encryptor::from_token(token).unwrap();
+            while let Ok(frame) = Frame::read_from(&mut stream).await {
+                if matches!(frame.frame_type, FrameType::LogBatch) && decryptor.decrypt(&frame.payload).is_err() {
+                    return;
+                }
+                Frame::ack().write_to(&mut stream).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    fn write_config(name: &str, server: &str, token: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ilog-cli-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, format!("[agent]\nserver = \"{}\"\ntoken = \"{}\"\n", server, token)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_connection_checks_token() {
+        let server = fake_server("right-token").await;
+        let good = write_config("good", &server, "right-token");
+        test_connection(&good).await.unwrap();
+
+        let server = fake_server("right-token").await;
+        let bad = write_config("bad", &server, "wrong-token");
+        let error = test_connection(&bad).await.unwrap_err();
+        assert!(error.to_string().starts_with("Token rejected"), "{:#}", error);
+
+        std::fs::remove_file(good).unwrap();
+        std::fs::remove_file(bad).unwrap();
+    }
+}
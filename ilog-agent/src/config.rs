@@ -1,22 +1,93 @@
-use serde::Deserialize;
-use std::path::PathBuf;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AgentConfig {
     pub agent: AgentSettings,
     #[serde(default)]
     pub sources: Sources,
+    #[serde(default)]
+    pub severity: SeverityConfig,
+    /// Applied in order to every entry before it is sent
+    #[serde(default)]
+    pub processors: Vec<ProcessorConfig>,
+    /// Host and agent attributes sent with every batch
+    #[serde(default)]
+    pub resource: ResourceConfig,
+    /// Memory limit of the queue between the providers and the processor chain
+    #[serde(default)]
+    pub buffer: BufferConfig,
+    /// Where trace and span ids are taken from
+    #[serde(default)]
+    pub trace_context: TraceContextConfig,
+    /// Fragments merged into this file, as glob patterns relative to its directory
+    #[serde(default = "default_include")]
+    pub include: Vec<String>,
+    /// Projects to send to besides the one in `[agent]`, by name
+    #[serde(default)]
+    pub outputs: BTreeMap<String, OutputConfig>,
+    /// Checked in order; entries no route matches go to the `[agent]` project
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+}
+
+/// Name routes use for the project configured in `[agent]`
+pub const DEFAULT_OUTPUT: &str = "default";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// Defaults to `agent.server`; outputs on the same server share a connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+    /// Set directly, or read from `token_file` or `token_env` when loading
+    #[serde(default)]
+    pub token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Sends the entries it matches to `output`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RouteConfig {
+    pub output: String,
+    /// Provider the entry comes from (its `source_type`), e.g. "docker"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Rules that must all match, e.g. `{ field = "k8s.namespace.name", pattern = "^billing$" }`
+    #[serde(default, rename = "match")]
+    pub matches: Vec<FilterRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct AgentSettings {
     pub server: String,
+    /// Set directly, or read from `token_file` or `token_env` when loading
+    #[serde(default)]
     pub token: String,
+    /// File holding the token, e.g. a mounted secret; relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
+    /// Environment variable holding the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
     #[serde(default = "default_protocol")]
     pub protocol: String,
+    /// Where providers persist resume positions
+    #[serde(default = "default_state_dir")]
+    pub state_dir: PathBuf,
+    /// How long shutdown waits for buffered logs to be sent
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Address for the `GET /status` health endpoint, e.g. "127.0.0.1:9881"
+    #[serde(default)]
+    pub status_addr: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct Sources {
     #[cfg(feature = "file")]
     pub file: Option<FileSource>,
@@ -24,41 +95,714 @@ pub struct Sources {
     pub journald: Option<JournaldSource>,
     #[cfg(feature = "docker")]
     pub docker: Option<DockerSource>,
+    #[cfg(feature = "syslog")]
+    pub syslog: Option<SyslogSource>,
+    #[cfg(feature = "otlp")]
+    pub otlp: Option<OtlpSource>,
+    #[cfg(feature = "kubernetes")]
+    pub kubernetes: Option<KubernetesSource>,
+    #[cfg(feature = "exec")]
+    pub exec: Option<ExecSource>,
+    #[cfg(feature = "socket")]
+    pub socket: Option<SocketSource>,
+    #[cfg(feature = "http-server")]
+    pub http: Option<HttpSource>,
 }
 
 #[cfg(feature = "file")]
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct FileSource {
     pub enabled: bool,
     pub paths: Vec<String>,
+    /// Service name template (default: the file name). Placeholders: `{file}`,
+    /// `{stem}` (file name without extension), `{dir}` (parent directory name)
+    /// and the groups captured by `path_pattern` (`{1}`, `{name}`)
+    pub service_name: Option<String>,
+    /// Regular expression matched against the full path, e.g. "^/var/www/(?P<app>[^/]+)/"
+    pub path_pattern: Option<String>,
 }
 
 #[cfg(feature = "journald")]
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct JournaldSource {
     pub enabled: bool,
+    /// Systemd units (".service" is appended when no suffix is given)
+    #[serde(default)]
     pub units: Vec<String>,
+    /// `SYSLOG_IDENTIFIER` values, matched as an alternative to `units`
+    #[serde(default)]
+    pub identifiers: Vec<String>,
+    /// journalctl-style priority filter: "warning" (and more severe) or "err..warning"
+    pub priority: Option<String>,
+    /// Arbitrary `FIELD=value` matches applied on top of units/identifiers
+    #[serde(default)]
+    pub matches: Vec<String>,
+    /// Journal fields forwarded as attributes ("_PID" becomes "pid")
+    #[serde(default = "default_journald_fields")]
+    pub fields: Vec<String>,
+    /// Service name template (default: the unit). Placeholders: `{unit}`
+    /// (without the ".service" suffix) and `{field:NAME}`, e.g. `{field:SYSLOG_IDENTIFIER}`
+    pub service_name: Option<String>,
+}
+
+#[cfg(feature = "journald")]
+fn default_journald_fields() -> Vec<String> {
+    vec!["_PID".to_string(), "_UID".to_string()]
 }
 
 #[cfg(feature = "docker")]
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct DockerSource {
     pub enabled: bool,
+    /// Container names or glob patterns
+    #[serde(default)]
     pub containers: Vec<String>,
+    /// Watch every running container (minus `exclude_names`)
+    #[serde(default)]
+    pub all: bool,
+    /// Label selectors, `key` or `key=value`
+    #[serde(default)]
+    pub include_labels: Vec<String>,
+    /// Container names or glob patterns that are never watched
+    #[serde(default)]
+    pub exclude_names: Vec<String>,
+    /// Container labels copied into log attributes
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Severity for stderr lines without a detected level (default: info)
+    pub stderr_severity: Option<String>,
+    /// Service name template (default: the container name). Placeholders: `{name}`,
+    /// `{image}`, `{compose.project}`, `{compose.service}` and `{label:KEY}`
+    pub service_name: Option<String>,
+}
+
+#[cfg(feature = "syslog")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SyslogSource {
+    pub enabled: bool,
+    /// UDP listen address, e.g. "127.0.0.1:514"
+    pub udp: Option<String>,
+    /// TCP listen address (octet-counted or newline-delimited framing)
+    pub tcp: Option<String>,
+    /// Unix datagram socket path, e.g. "/run/ilog-agent/syslog.sock"
+    pub unix: Option<PathBuf>,
+}
+
+#[cfg(feature = "otlp")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OtlpSource {
+    pub enabled: bool,
+    /// OTLP/HTTP listen address (JSON and protobuf on `/v1/logs`), e.g. "127.0.0.1:4318"
+    pub http: Option<String>,
+    /// OTLP/gRPC listen address, e.g. "127.0.0.1:4317"
+    pub grpc: Option<String>,
+}
+
+#[cfg(feature = "kubernetes")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct KubernetesSource {
+    pub enabled: bool,
+    /// Kubelet pod log directory (`<namespace>_<pod>_<uid>/<container>/<n>.log`)
+    #[serde(default = "default_pod_log_dir")]
+    pub log_dir: PathBuf,
+    /// Only collect these namespaces (default: all)
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    #[serde(default)]
+    pub exclude_namespaces: Vec<String>,
+    /// Kubelet API for pod labels, images and node name, e.g. "https://127.0.0.1:10250"
+    pub kubelet_url: Option<String>,
+    #[serde(default = "default_kubelet_token_file")]
+    pub kubelet_token_file: PathBuf,
+    /// Skip certificate verification (kubelets usually serve self-signed certs)
+    #[serde(default)]
+    pub kubelet_insecure: bool,
+}
+
+#[cfg(feature = "kubernetes")]
+fn default_pod_log_dir() -> PathBuf {
+    PathBuf::from("/var/log/pods")
+}
+
+#[cfg(feature = "kubernetes")]
+fn default_kubelet_token_file() -> PathBuf {
+    PathBuf::from("/var/run/secrets/kubernetes.io/serviceaccount/token")
+}
+
+#[cfg(feature = "exec")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExecSource {
+    pub enabled: bool,
+    #[serde(default)]
+    pub commands: Vec<ExecCommand>,
+}
+
+#[cfg(feature = "exec")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExecCommand {
+    /// Service name for the captured output
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// First restart delay, doubled after each quick exit
+    #[serde(default = "default_backoff_initial_secs")]
+    pub backoff_initial_secs: u64,
+    #[serde(default = "default_backoff_max_secs")]
+    pub backoff_max_secs: u64,
+    /// Severity for stderr lines without a detected level (default: info)
+    pub stderr_severity: Option<String>,
+}
+
+#[cfg(feature = "exec")]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+#[cfg(feature = "exec")]
+fn default_backoff_initial_secs() -> u64 {
+    1
+}
+
+#[cfg(feature = "exec")]
+fn default_backoff_max_secs() -> u64 {
+    60
+}
+
+#[cfg(feature = "socket")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SocketSource {
+    pub enabled: bool,
+    /// Unix stream socket accepting NDJSON or plain lines
+    pub path: Option<PathBuf>,
+    /// Named pipe read as NDJSON or plain lines (created if missing)
+    pub fifo: Option<PathBuf>,
+    /// Octal permissions applied to the socket and FIFO
+    #[serde(default = "default_socket_mode")]
+    pub mode: String,
+    /// Service name for records that do not carry one
+    #[serde(default = "default_socket_service")]
+    pub service: String,
+}
+
+#[cfg(feature = "socket")]
+fn default_socket_mode() -> String {
+    "0660".to_string()
+}
+
+#[cfg(feature = "socket")]
+fn default_socket_service() -> String {
+    "socket".to_string()
+}
+
+#[cfg(feature = "http-server")]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct HttpSource {
+    pub enabled: bool,
+    #[serde(default = "default_http_listen")]
+    pub listen: String,
+    #[serde(default = "default_http_path")]
+    pub path: String,
+    /// Shared secret expected as `Authorization: Bearer <token>` or `X-Ilog-Token`
+    pub token: Option<String>,
+    /// Service name for records that do not carry one
+    #[serde(default = "default_http_service")]
+    pub service: String,
+    #[serde(default = "default_http_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+#[cfg(feature = "http-server")]
+fn default_http_listen() -> String {
+    "127.0.0.1:9880".to_string()
+}
+
+#[cfg(feature = "http-server")]
+fn default_http_path() -> String {
+    "/logs".to_string()
+}
+
+#[cfg(feature = "http-server")]
+fn default_http_service() -> String {
+    "http".to_string()
+}
+
+#[cfg(feature = "http-server")]
+fn default_http_max_body_bytes() -> usize {
+    5 * 1024 * 1024
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ResourceConfig {
+    /// Overrides the detected `host.name`
+    pub hostname: Option<String>,
+    /// Static labels such as `deployment.environment` or `cloud.region`;
+    /// these win over detected attributes with the same key
+    #[serde(default)]
+    pub attributes: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct BufferConfig {
+    /// Approximate bytes of entries held in memory before the overflow policy applies
+    #[serde(default = "default_memory_limit_bytes")]
+    pub memory_limit_bytes: usize,
+    /// Policy for sources not listed in `sources`
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    /// Policy per source, keyed by its `[sources.<name>]` name
+    #[serde(default)]
+    pub sources: HashMap<String, OverflowPolicy>,
+    /// Where `spill` sources keep entries (default: `<state_dir>/spill`)
+    pub spill_dir: Option<PathBuf>,
+    /// Disk space for spilled entries; beyond it they are dropped
+    #[serde(default = "default_spill_limit_bytes")]
+    pub spill_limit_bytes: u64,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            memory_limit_bytes: default_memory_limit_bytes(),
+            overflow: OverflowPolicy::default(),
+            sources: HashMap::new(),
+            spill_dir: None,
+            spill_limit_bytes: default_spill_limit_bytes(),
+        }
+    }
+}
+
+/// What a source does when the buffer is full
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for space, slowing the source down
+    #[default]
+    Block,
+    /// Discard the incoming entry
+    DropNewest,
+    /// Discard the source's oldest queued entries to make room
+    DropOldest,
+    /// Write the entry to disk and send it once memory frees up
+    Spill,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct SeverityConfig {
+    /// Extra or overriding keywords, e.g. `FAILED = "error"` or `AUDIT = "10"`
+    #[serde(default)]
+    pub keywords: HashMap<String, String>,
+}
+
+/// Trace context extraction, applied to every entry before the processors.
+/// Fields are looked up in the attributes, then in messages that are JSON objects.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TraceContextConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Fields holding the trace id; the first valid one wins
+    #[serde(default = "default_trace_id_fields")]
+    pub trace_id_fields: Vec<String>,
+    #[serde(default = "default_span_id_fields")]
+    pub span_id_fields: Vec<String>,
+    #[serde(default = "default_trace_flags_fields")]
+    pub trace_flags_fields: Vec<String>,
+    /// Fields holding a W3C `traceparent` value (`00-<trace id>-<span id>-<flags>`)
+    #[serde(default = "default_traceparent_fields")]
+    pub traceparent_fields: Vec<String>,
+    /// Also look for a `traceparent` value anywhere in the message text
+    #[serde(default = "default_true")]
+    pub message_traceparent: bool,
+}
+
+impl Default for TraceContextConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            trace_id_fields: default_trace_id_fields(),
+            span_id_fields: default_span_id_fields(),
+            trace_flags_fields: default_trace_flags_fields(),
+            traceparent_fields: default_traceparent_fields(),
+            message_traceparent: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|s| s.to_string()).collect()
+}
+
+fn default_trace_id_fields() -> Vec<String> {
+    strings(&["trace_id", "traceId", "trace.id", "otelTraceID"])
+}
+
+fn default_span_id_fields() -> Vec<String> {
+    strings(&["span_id", "spanId", "span.id", "otelSpanID"])
+}
+
+fn default_trace_flags_fields() -> Vec<String> {
+    strings(&["trace_flags", "traceFlags", "otelTraceSampled"])
+}
+
+fn default_traceparent_fields() -> Vec<String> {
+    strings(&["traceparent"])
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProcessorConfig {
+    Filter(FilterConfig),
+    Sample(SampleConfig),
+    RateLimit(RateLimitConfig),
+    Redact(RedactConfig),
+    Dedup(DedupConfig),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FilterConfig {
+    /// Keep only entries matching at least one of these (when non-empty)
+    #[serde(default)]
+    pub include: Vec<FilterRule>,
+    /// Drop entries matching any of these
+    #[serde(default)]
+    pub exclude: Vec<FilterRule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FilterRule {
+    /// `message`, `service`, `severity` or an attribute name
+    pub field: String,
+    /// Regular expression matched against the field value
+    pub pattern: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SampleConfig {
+    /// Fraction of entries kept, 0.0 - 1.0
+    pub rate: f64,
+    /// Field hashed to decide, so equal values are kept or dropped together
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    /// Field whose values get their own bucket
+    #[serde(default = "default_rate_limit_key")]
+    pub key: String,
+    /// Entries per second
+    pub rate: f64,
+    /// Bucket size (default: `rate`)
+    pub burst: Option<f64>,
+}
+
+fn default_rate_limit_key() -> String {
+    "source_type".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RedactConfig {
+    /// Built-in detectors: `email`, `credit_card`, `jwt`, `aws_key`, `ip`,
+    /// `bearer`, `password` (default: all)
+    #[serde(default = "default_redact_detectors")]
+    pub detectors: Vec<String>,
+    /// What to do with a match (default: `mask`)
+    #[serde(default)]
+    pub mode: RedactMode,
+    /// Additional patterns
+    #[serde(default)]
+    pub rules: Vec<RedactRule>,
+    /// Prepended to values before hashing, so hashes can't be reversed by lookup
+    pub hash_salt: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RedactRule {
+    /// Shown in the replacement, e.g. `[REDACTED:<name>]`
+    pub name: String,
+    /// Regular expression; the whole match is replaced, or only its `secret` group if it has one
+    pub pattern: String,
+    /// Overrides the processor's mode for this rule
+    pub mode: Option<RedactMode>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactMode {
+    /// Replace with `[REDACTED:<name>]`
+    #[default]
+    Mask,
+    /// Replace with a salted hash, so equal values can still be correlated
+    Hash,
+    /// Drop the whole entry
+    Drop,
+}
+
+fn default_redact_detectors() -> Vec<String> {
+    ["email", "credit_card", "jwt", "aws_key", "ip", "bearer", "password"].iter().map(|s| s.to_string()).collect()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DedupConfig {
+    /// Field whose values are deduplicated separately
+    #[serde(default = "default_dedup_key")]
+    pub key: String,
+    /// Longest stretch of repeats collapsed into one entry, in seconds
+    #[serde(default = "default_dedup_window_secs")]
+    pub window_secs: u64,
+    /// Treat messages that differ only in numbers as repeats
+    #[serde(default)]
+    pub normalize_numbers: bool,
+}
+
+fn default_dedup_key() -> String {
+    "service".to_string()
+}
+
+fn default_dedup_window_secs() -> u64 {
+    10
 }
 
 fn default_protocol() -> String {
     "tcp".to_string()
 }
 
+fn default_include() -> Vec<String> {
+    vec!["conf.d/*.toml".to_string()]
+}
+
+fn default_state_dir() -> PathBuf {
+    PathBuf::from("/var/lib/ilog-agent")
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    10
+}
+
+fn default_memory_limit_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_spill_limit_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+/// Prefix of environment overrides: `ILOG_AGENT_<SECTION>__<KEY>`, with `__`
+/// between nested keys so keys containing `_` can be reached
+const ENV_PREFIX: &str = "ILOG_AGENT";
+
+/// Shorthands documented before nested overrides existed
+const ENV_ALIASES: &[(&str, &str)] = &[("ILOG_AGENT_SERVER", "agent.server"), ("ILOG_AGENT_TOKEN", "agent.token")];
+
+fn read_toml(path: &Path) -> Result<toml::Table> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Cannot read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid TOML in {}", path.display()))
+}
+
+/// Merge `fragment` into `base`: tables are merged key by key, arrays are
+/// appended (so fragments can add file paths or exec commands), anything else
+/// is replaced
+fn merge(base: &mut toml::Table, fragment: toml::Table) {
+    for (key, value) in fragment {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(table)) => merge(existing, table),
+            (Some(toml::Value::Array(existing)), toml::Value::Array(items)) => existing.extend(items),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Fragment files matched by `include` patterns of the main file, in name order
+fn fragments(path: &Path, main: &toml::Table) -> Result<Vec<PathBuf>> {
+    let patterns: Vec<String> = match main.get("include") {
+        Some(value) => value
+            .clone()
+            .try_into()
+            .context("include: expected a list of glob patterns")?,
+        None => default_include(),
+    };
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut files = Vec::new();
+    for pattern in &patterns {
+        let pattern = dir.join(pattern);
+        let matches = glob::glob(&pattern.to_string_lossy()).with_context(|| format!("include: invalid pattern {}", pattern.display()))?;
+        let mut matched: Vec<PathBuf> = matches.filter_map(|entry| entry.ok()).filter(|path| path.is_file()).collect();
+        matched.sort();
+        files.extend(matched);
+    }
+    Ok(files)
+}
+
+/// Fill `token` from `file` (relative to `dir`) or `env`; exactly one of the
+/// three must be set. `key` names the section in errors.
+fn resolve_token(key: &str, token: &mut String, file: Option<&Path>, env: Option<&str>, dir: &Path) -> Result<()> {
+    match [!token.is_empty(), file.is_some(), env.is_some()].iter().filter(|set| **set).count() {
+        0 => anyhow::bail!("{}.token: missing, set token, token_file or token_env", key),
+        1 => {}
+        _ => anyhow::bail!("{}.token: set only one of token, token_file and token_env", key),
+    }
+
+    if let Some(path) = file {
+        let path = dir.join(path);
+        let text = std::fs::read_to_string(&path).with_context(|| format!("{}.token_file: cannot read {}", key, path.display()))?;
+        *token = text.trim().to_string();
+    } else if let Some(name) = env {
+        *token = std::env::var(name).with_context(|| format!("{}.token_env: ${} is not set", key, name))?;
+    }
+    anyhow::ensure!(!token.is_empty(), "{}.token: empty", key);
+    Ok(())
+}
+
+fn validate_server(key: &str, server: &str) -> Result<()> {
+    let port = server.rsplit_once(':').map(|(_, port)| port.parse::<u16>());
+    anyhow::ensure!(matches!(port, Some(Ok(_))), "{}.server: expected host:port, got {:?}", key, server);
+    Ok(())
+}
+
 impl AgentConfig {
-    pub fn load(path: &PathBuf) -> Result<Self, config::ConfigError> {
-        let config = config::Config::builder()
+    /// Resolve tokens and check what deserialization alone cannot
+    fn validate(&mut self, dir: &Path) -> Result<()> {
+        let agent = &mut self.agent;
+        resolve_token("agent", &mut agent.token, agent.token_file.as_deref(), agent.token_env.as_deref(), dir)?;
+        validate_server("agent", &agent.server)?;
+
+        for (name, output) in &mut self.outputs {
+            let key = format!("outputs.{}", name);
+            anyhow::ensure!(name != DEFAULT_OUTPUT, "{}: the name is reserved for [agent]", key);
+            resolve_token(&key, &mut output.token, output.token_file.as_deref(), output.token_env.as_deref(), dir)?;
+            if let Some(server) = &output.server {
+                validate_server(&key, server)?;
+            }
+        }
+
+        for (i, route) in self.routes.iter().enumerate() {
+            anyhow::ensure!(
+                route.output == DEFAULT_OUTPUT || self.outputs.contains_key(&route.output),
+                "routes[{}].output: no output named '{}'",
+                i,
+                route.output
+            );
+        }
+        Ok(())
+    }
+
+    /// The main file and the fragments it includes, for change detection
+    pub fn files(path: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = vec![path.to_path_buf()];
+        files.extend(fragments(path, &read_toml(path)?)?);
+        Ok(files)
+    }
+
+    /// Load `path`, merge the `include`d fragments (`conf.d/*.toml` by default)
+    /// and apply environment overrides
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with_env(path, std::env::vars().collect())
+    }
+
+    /// `load` with the environment passed in instead of read from the process
+    fn load_with_env(path: &Path, env: HashMap<String, String>) -> Result<Self> {
+        let mut table = read_toml(path)?;
+        for fragment in fragments(path, &table)? {
+            merge(&mut table, read_toml(&fragment)?);
+        }
+
+        let mut builder = config::Config::builder()
             .set_default("agent.protocol", "tcp")?
-            .add_source(config::File::from(path.clone()))
-            .add_source(config::Environment::with_prefix("ILOG_AGENT").separator("_"))
-            .build()?;
+            .add_source(config::File::from_str(&toml::to_string(&table)?, config::FileFormat::Toml))
+            .add_source(
+                config::Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("_")
+                    .separator("__")
+                    .source(Some(env.clone())),
+            );
+        for (variable, key) in ENV_ALIASES {
+            if let Some(value) = env.get(*variable) {
+                builder = builder.set_override(*key, value.as_str())?;
+            }
+        }
+
+        // The error names the key that failed, e.g. "buffer.overflow: unknown variant"
+        let mut config: Self = serde_path_to_error::deserialize(builder.build()?).map_err(|e| {
+            let path = e.path().to_string();
+            let inner = e.into_inner();
+            if path == "." {
+                anyhow::anyhow!("{}", inner)
+            } else {
+                anyhow::anyhow!("{}: {}", path, inner)
+            }
+        })?;
+        config.validate(path.parent().unwrap_or(Path::new(".")))?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ilog-config-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("conf.d")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_fragments_and_env() {
+        let dir = config_dir("fragments");
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[agent]\nserver = \"localhost:8080\"\ntoken_file = \"token\"\n\n[[processors]]\ntype = \"sample\"\nrate = 0.5\n").unwrap();
+        std::fs::write(dir.join("token"), "secret-token\n").unwrap();
+        std::fs::write(dir.join("conf.d/10-audit.toml"), "[[processors]]\ntype = \"sample\"\nrate = 0.1\n\n[buffer.sources]\nexec = \"spill\"\n").unwrap();
+        std::fs::write(dir.join("conf.d/20-buffer.toml"), "[buffer]\noverflow = \"drop_oldest\"\n").unwrap();
+        let env = HashMap::from([("ILOG_AGENT_TRACE_CONTEXT__MESSAGE_TRACEPARENT".to_string(), "false".to_string())]);
+
+        let config = AgentConfig::load_with_env(&path, env).unwrap();
+        assert_eq!(config.agent.token, "secret-token");
+        // Arrays from fragments are appended, tables merged
+        assert_eq!(config.processors.len(), 2);
+        assert_eq!(config.buffer.overflow, OverflowPolicy::DropOldest);
+        assert_eq!(config.buffer.sources["exec"], OverflowPolicy::Spill);
+        assert!(!config.trace_context.message_traceparent);
+        assert_eq!(AgentConfig::files(&path).unwrap().len(), 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        let dir = config_dir("errors");
+        let path = dir.join("config.toml");
+        let load = |text: &str| {
+            std::fs::write(&path, text).unwrap();
+            AgentConfig::load(&path).unwrap_err().to_string()
+        };
+
+        let error = load("[agent]\nserver = \"localhost:8080\"\ntoken = \"t\"\n[buffer]\noverflow = \"sometimes\"\n");
+        assert!(error.starts_with("buffer.overflow:") && error.contains("sometimes"), "{}", error);
+        let error = load("[agent]\nserver = \"localhost\"\ntoken = \"t\"\n");
+        assert!(error.starts_with("agent.server:"), "{}", error);
+        let error = load("[agent]\nserver = \"localhost:8080\"\ntoken_env = \"ILOG_TEST_UNSET_TOKEN\"\n");
+        assert_eq!(error, "agent.token_env: $ILOG_TEST_UNSET_TOKEN is not set");
+        let error = load("[agent]\nserver = \"localhost:8080\"\n");
+        assert!(error.starts_with("agent.token: missing"), "{}", error);
 
-        config.try_deserialize()
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -57,6 +57,7 @@ impl Encryptor {
         Ok(result)
     }
 
+    #[cfg(test)]
     pub fn decrypt(&self, encrypted: &[u8]) -> Result<Vec<u8>> {
         if encrypted.len() < NONCE_SIZE {
             anyhow::bail!("Encrypted data too short");
@@ -0,0 +1,198 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::tcp_sender::LogEntry;
+
+/// First delay after a failure; doubled on every further failure
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay between restarts. A run that lasted longer than this
+/// counts as healthy, so the next failure starts from `INITIAL_BACKOFF` again.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    Running,
+    /// Failed, waiting before the next restart
+    Backoff,
+    /// Finished on its own and will not be restarted
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub state: State,
+    pub restarts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    /// When the component entered its current state
+    pub since: DateTime<Utc>,
+}
+
+/// State of every provider (`file`) and tailer (`file:/var/log/app.log`)
+/// and the entries each source lost to a full buffer, shared by the
+/// supervisor, the status endpoint and the heartbeat
+#[derive(Default)]
+pub struct Health {
+    components: Mutex<BTreeMap<String, ComponentHealth>>,
+    dropped: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Health {
+    pub fn set(&self, name: &str, state: State) {
+        let mut components = self.components.lock().unwrap();
+        let component = components.entry(name.to_string()).or_insert_with(|| ComponentHealth {
+            state,
+            restarts: 0,
+            last_error: None,
+            since: Utc::now(),
+        });
+        if component.state != state {
+            component.state = state;
+            component.since = Utc::now();
+        }
+    }
+
+    fn failed(&self, name: &str, error: &anyhow::Error) {
+        self.set(name, State::Backoff);
+        let mut components = self.components.lock().unwrap();
+        if let Some(component) = components.get_mut(name) {
+            component.restarts += 1;
+            component.last_error = Some(format!("{:#}", error));
+        }
+    }
+
+    /// Forget a provider and its tailers
+    pub fn remove(&self, name: &str) {
+        let prefix = format!("{}:", name);
+        self.components
+            .lock()
+            .unwrap()
+            .retain(|key, _| key != name && !key.starts_with(&prefix));
+    }
+
+    /// Count an entry from `source` that was dropped; returns the source's total
+    pub fn add_dropped(&self, source: &str) -> u64 {
+        let mut dropped = self.dropped.lock().unwrap();
+        let count = dropped.entry(source.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// `{"status": "ok" | "degraded", "components": {...}, "dropped": {...}}`
+    pub fn snapshot(&self) -> serde_json::Value {
+        let components = self.components.lock().unwrap();
+        let degraded = components.values().any(|c| c.state == State::Backoff);
+        serde_json::json!({
+            "status": if degraded { "degraded" } else { "ok" },
+            "components": &*components,
+            "dropped": &*self.dropped.lock().unwrap(),
+        })
+    }
+}
+
+/// Delays between restarts: 1s, 2s, 4s, ... up to `MAX_BACKOFF`
+struct Backoff {
+    next: Duration,
+}
+
+impl Backoff {
+    fn new() -> Self {
+        Self { next: INITIAL_BACKOFF }
+    }
+
+    fn next_delay(&mut self, ran_for: Duration) -> Duration {
+        if ran_for >= MAX_BACKOFF {
+            self.next = INITIAL_BACKOFF;
+        }
+        let delay = self.next;
+        self.next = (self.next * 2).min(MAX_BACKOFF);
+        delay
+    }
+}
+
+/// Run `task` under `name`, restarting it with exponential backoff whenever
+/// it fails. Returns once it finishes without error or `tx` is closed.
+pub async fn supervise<F, Fut>(health: &Health, name: &str, tx: &mpsc::Sender<LogEntry>, mut task: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut backoff = Backoff::new();
+    loop {
+        health.set(name, State::Running);
+        let started = Instant::now();
+        let result = task().await;
+
+        if tx.is_closed() {
+            return;
+        }
+        let Err(e) = result else {
+            info!("{} finished", name);
+            health.set(name, State::Stopped);
+            return;
+        };
+
+        let delay = backoff.next_delay(started.elapsed());
+        error!("{} failed: {:#}; restarting in {:?}", name, e, delay);
+        health.failed(name, &e);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = tx.closed() => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::new();
+        let quick = Duration::from_millis(10);
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay(quick).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+
+        // A long healthy run starts over
+        assert_eq!(backoff.next_delay(MAX_BACKOFF), INITIAL_BACKOFF);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_supervise_restarts_until_success() {
+        let health = Health::default();
+        let (tx, _rx) = mpsc::channel(1);
+        let mut attempts = 0;
+
+        supervise(&health, "file:/var/log/app.log", &tx, || {
+            attempts += 1;
+            let fail = attempts < 3;
+            async move {
+                if fail {
+                    anyhow::bail!("attempt failed");
+                }
+                Ok(())
+            }
+        })
+        .await;
+
+        assert_eq!(attempts, 3);
+        let snapshot = health.snapshot();
+        let component = &snapshot["components"]["file:/var/log/app.log"];
+        assert_eq!(component["state"], "stopped");
+        assert_eq!(component["restarts"], 2);
+        assert_eq!(component["last_error"], "attempt failed");
+        assert_eq!(snapshot["status"], "ok");
+
+        health.remove("file");
+        assert_eq!(health.snapshot()["components"], serde_json::json!({}));
+    }
+}
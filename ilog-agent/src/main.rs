@@ -1,49 +1,129 @@
+mod buffer;
+mod cli;
 mod config;
 mod tcp_sender;
 mod crypto;
+mod health;
+mod output;
 mod protocol;
+mod processors;
 mod providers;
+mod resource;
+#[cfg(any(feature = "file", feature = "docker", feature = "journald"))]
+mod service_name;
+mod severity;
+mod status;
+mod supervisor;
+#[cfg(any(feature = "socket", feature = "http-server"))]
+mod record;
+#[cfg(any(feature = "file", feature = "docker", feature = "journald", feature = "kubernetes"))]
+mod state;
+
+#[cfg(not(any(
+    feature = "file",
+    feature = "journald",
+    feature = "docker",
+    feature = "syslog",
+    feature = "otlp",
+    feature = "kubernetes",
+    feature = "exec",
+    feature = "socket",
+    feature = "http-server"
+)))]
+compile_error!("no log source enabled; build with at least one source feature, e.g. --features file");
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
-use tracing::{error, info};
-use tracing_subscriber;
+use tracing::{error, info, warn};
 
 use config::AgentConfig;
-use tcp_sender::TcpLogSender;
-use providers::LogProvider;
+
+/// Entries between the processor chain and the sender; the memory limit
+/// applies to the buffer in front of the chain
+const PROCESSED_CHANNEL: usize = 100;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// Path to configuration file
-    #[arg(short, long, value_name = "FILE")]
+    #[arg(short, long, value_name = "FILE", global = true, default_value = "/etc/ilog/config.toml")]
     config: PathBuf,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Collect and send logs (the default)
+    Run {
+        /// Print entries to stdout as JSON lines instead of sending them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Validate the configuration and print it with defaults and environment overrides applied
+    CheckConfig,
+    /// Connect to the server, check the token and report latency
+    TestConnection,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
-
     let args = Args::parse();
+    let command = args.command.unwrap_or(Command::Run { dry_run: false });
 
-    let config = AgentConfig::load(&args.config)?;
-    info!("Loaded configuration from {:?}", args.config);
+    // Keep stdout for the command's own output
+    if matches!(command, Command::Run { dry_run: false }) {
+        tracing_subscriber::fmt::init();
+    } else {
+        tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+    }
+
+    match command {
+        Command::Run { dry_run } => run(args.config, dry_run).await,
+        Command::CheckConfig => cli::check_config(&args.config).await,
+        Command::TestConnection => cli::test_connection(&args.config).await,
+    }
+}
+
+async fn run(config_path: PathBuf, dry_run: bool) -> Result<()> {
+    let config = AgentConfig::load(&config_path)?;
+    info!("Loaded configuration from {:?}", config_path);
     info!("Server: {}", config.agent.server);
 
     let config = Arc::new(config);
+    let health = Arc::new(health::Health::default());
+
+    if let Some(addr) = config.agent.status_addr.clone() {
+        let health = health.clone();
+        tokio::spawn(async move {
+            if let Err(e) = status::serve(addr, health).await {
+                error!("Status endpoint error: {:#}", e);
+            }
+        });
+    }
 
-    let (tx, rx) = mpsc::channel(1000);
+    // Providers -> buffer (bounded in bytes) -> processor chain -> sender
+    let (buffer, raw_rx) = buffer::Buffer::start(&config, health.clone())?;
+    let (processed_tx, rx) = mpsc::channel(PROCESSED_CHANNEL);
+    let chain = processors::ProcessorChain::from_config(&config.processors)?.with_trace_context(&config.trace_context)?;
+    tokio::spawn(chain.run(raw_rx, processed_tx));
 
     let config_clone = config.clone();
-    let sender_handle = tokio::spawn(async move {
+    let sender_health = health.clone();
+    let mut sender_handle = tokio::spawn(async move {
+        if dry_run {
+            info!("Dry run: printing entries to stdout instead of sending them");
+            return cli::print_entries(rx).await;
+        }
         match config_clone.agent.protocol.as_str() {
             "tcp" => {
                 info!("Using TCP protocol with ChaCha20-Poly1305 encryption and LZ4 compression");
-                TcpLogSender::start(config_clone, rx).await
+                output::start(config_clone, rx, sender_health).await
             }
             "http" => {
                 error!("HTTP protocol is deprecated, use TCP instead");
@@ -56,65 +136,34 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Collect all enabled providers
-    let mut provider_handles = vec![];
-
-    // File provider
-    #[cfg(feature = "file")]
-    {
-        if config.sources.file.as_ref().map(|f| f.enabled).unwrap_or(false) {
-            let provider = providers::file::FileProvider::new(config.clone());
-            let tx_clone = tx.clone();
-            info!("Starting {} provider", provider.name());
-            let handle = tokio::spawn(async move {
-                if let Err(e) = provider.start(tx_clone).await {
-                    error!("File provider error: {}", e);
-                }
-            });
-            provider_handles.push(handle);
+    let mut supervisor = supervisor::Supervisor::new(config.clone(), buffer, health);
+    supervisor.start_all();
+
+    // Apply config changes until a shutdown signal (or the sender stopping)
+    let mut reloads = supervisor::reload_triggers(config_path.clone());
+    let shutdown = supervisor::shutdown_signal();
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            result = &mut sender_handle => return result?,
+            Some(()) = reloads.recv() => match AgentConfig::load(&config_path) {
+                Ok(new) => supervisor.reload(Arc::new(new)).await,
+                Err(e) => error!("Keeping the running configuration, {:?} is invalid: {:#}", config_path, e),
+            },
+            _ = &mut shutdown => break,
         }
     }
 
-    // Docker provider
-    #[cfg(feature = "docker")]
-    {
-        if config.sources.docker.as_ref().map(|d| d.enabled).unwrap_or(false) {
-            let provider = providers::docker::DockerProvider::new(config.clone());
-            let tx_clone = tx.clone();
-            info!("Starting {} provider", provider.name());
-            let handle = tokio::spawn(async move {
-                if let Err(e) = provider.start(tx_clone).await {
-                    error!("Docker provider error: {}", e);
-                }
-            });
-            provider_handles.push(handle);
-        }
-    }
+    // Providers stop first; the chain and the sender then drain the channels
+    info!("Shutting down...");
+    supervisor.shutdown().await;
 
-    // Systemd provider (Linux only)
-    #[cfg(all(feature = "journald", target_os = "linux"))]
-    {
-        if config.sources.journald.as_ref().map(|j| j.enabled).unwrap_or(false) {
-            let provider = providers::systemd::SystemdProvider::new(config.clone());
-            let tx_clone = tx.clone();
-            info!("Starting {} provider", provider.name());
-            let handle = tokio::spawn(async move {
-                if let Err(e) = provider.start(tx_clone).await {
-                    error!("Systemd provider error: {}", e);
-                }
-            });
-            provider_handles.push(handle);
-        }
+    let deadline = Duration::from_secs(config.agent.shutdown_timeout_secs);
+    match tokio::time::timeout(deadline, sender_handle).await {
+        Ok(result) => result??,
+        Err(_) => warn!("Buffered logs were not sent within {:?}, exiting anyway", deadline),
     }
-
-    info!("Started {} providers", provider_handles.len());
-
-    // Wait for sender to complete
-    sender_handle.await??;
-
-    // Wait for shutdown signal
-    tokio::signal::ctrl_c().await?;
-    info!("Shutting down...");
+    info!("Shutdown complete");
 
     Ok(())
 }
@@ -0,0 +1,219 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::config::{AgentConfig, DEFAULT_OUTPUT};
+use crate::health::Health;
+use crate::processors::{field_value, Rule};
+use crate::tcp_sender::{Connection, LogEntry, TcpLogSender};
+
+/// Entries waiting for each output's sender
+const OUTPUT_CHANNEL: usize = 100;
+
+/// A project entries can be sent to: `[agent]` or one of `[outputs.*]`
+pub struct Target {
+    pub name: String,
+    pub server: String,
+    pub token: String,
+}
+
+/// Every output, the `[agent]` project first
+pub fn targets(config: &AgentConfig) -> Vec<Target> {
+    let default = Target {
+        name: DEFAULT_OUTPUT.to_string(),
+        server: config.agent.server.clone(),
+        token: config.agent.token.clone(),
+    };
+    let named = config.outputs.iter().map(|(name, output)| Target {
+        name: name.clone(),
+        server: output.server.clone().unwrap_or_else(|| config.agent.server.clone()),
+        token: output.token.clone(),
+    });
+    std::iter::once(default).chain(named).collect()
+}
+
+struct Route {
+    /// Index into `targets`
+    output: usize,
+    source: Option<String>,
+    rules: Vec<Rule>,
+}
+
+/// Picks the output of each entry: the first matching route, else `[agent]`
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn from_config(config: &AgentConfig, targets: &[Target]) -> Result<Self> {
+        let routes = config
+            .routes
+            .iter()
+            .enumerate()
+            .map(|(i, route)| {
+                let output = targets
+                    .iter()
+                    .position(|target| target.name == route.output)
+                    .with_context(|| format!("routes[{}].output: no output named '{}'", i, route.output))?;
+                let rules = route
+                    .matches
+                    .iter()
+                    .map(Rule::from_config)
+                    .collect::<Result<_>>()
+                    .with_context(|| format!("routes[{}]", i))?;
+                Ok(Route {
+                    output,
+                    source: route.source.clone(),
+                    rules,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { routes })
+    }
+
+    pub fn route(&self, entry: &LogEntry) -> usize {
+        self.routes
+            .iter()
+            .find(|route| {
+                route
+                    .source
+                    .as_ref()
+                    .is_none_or(|source| field_value(entry, "source_type").as_deref() == Some(source.as_str()))
+                    && route.rules.iter().all(|rule| rule.matches(entry))
+            })
+            .map_or(0, |route| route.output)
+    }
+}
+
+/// Route entries to one sender per output until `rx` closes, then wait for
+/// every sender to flush. Outputs on the same server share one connection; the
+/// server tells them apart by the token each batch is encrypted with. The
+/// first output on each server owns the connection and sends its heartbeat.
+pub async fn start(config: Arc<AgentConfig>, mut rx: mpsc::Receiver<LogEntry>, health: Arc<Health>) -> Result<()> {
+    let targets = targets(&config);
+    let router = Router::from_config(&config, &targets)?;
+
+    let mut connections: HashMap<String, Connection> = HashMap::new();
+    let mut senders = Vec::new();
+    let mut handles = Vec::new();
+    for target in &targets {
+        let owner = !connections.contains_key(&target.server);
+        let connection = connections.entry(target.server.clone()).or_default().clone();
+        let sender = TcpLogSender::new(&config, target, connection)?;
+        let (tx, output_rx) = mpsc::channel(OUTPUT_CHANNEL);
+        senders.push(tx);
+        handles.push(tokio::spawn(sender.start(output_rx, owner.then(|| health.clone()))));
+    }
+    if targets.len() > 1 {
+        info!("Sending to {} outputs over {} connection(s)", targets.len(), connections.len());
+    }
+
+    while let Some(entry) = rx.recv().await {
+        let output = router.route(&entry);
+        if senders[output].send(entry).await.is_err() {
+            warn!("Sender for output {} stopped", targets[output].name);
+            break;
+        }
+    }
+
+    // Closing the channels makes every sender flush and return
+    drop(senders);
+    let mut result = Ok(());
+    for (handle, target) in handles.into_iter().zip(&targets) {
+        if let Err(e) = handle.await? {
+            warn!("Output {} failed: {:#}", target.name, e);
+            result = Err(e);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::Encryptor;
+    use crate::processors::test_entry;
+    use crate::protocol::{Frame, FrameType};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_routes() {
+        let config: AgentConfig = toml::from_str(
+            r#"
+            [agent]
+            server = "localhost:8080"
+            token = "main"
+
+            [outputs.billing]
+            token = "billing"
+
+            [outputs.audit]
+            server = "audit.internal:8080"
+            token = "audit"
+
+            [[routes]]
+            output = "audit"
+            source = "journald"
+
+            [[routes]]
+            output = "billing"
+            match = [{ field = "k8s.namespace.name", pattern = "^billing$" }]
+            "#,
+        )
+        .unwrap();
+        let targets = targets(&config);
+        assert_eq!(targets.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["default", "audit", "billing"]);
+        assert_eq!(targets[2].server, "localhost:8080");
+        let router = Router::from_config(&config, &targets).unwrap();
+
+        let mut entry = test_entry("charged", json!({ "source_type": "kubernetes" }));
+        entry.resource = Some(serde_json::Map::from_iter([("k8s.namespace.name".to_string(), "billing".into())]));
+        assert_eq!(router.route(&entry), 2);
+        assert_eq!(router.route(&test_entry("sshd", json!({ "source_type": "journald" }))), 1);
+        assert_eq!(router.route(&test_entry("other", json!({ "source_type": "file" }))), 0);
+    }
+
+    #[tokio::test]
+    async fn test_outputs_share_a_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = listener.local_addr().unwrap().to_string();
+        let (frames_tx, mut frames) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // A single accept: a second connection would never be served
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let tokens = ["main", "billing"].map(|token| (token, Encryptor::from_token(token).unwrap()));
+            while let Ok(frame) = Frame::read_from(&mut stream).await {
+                let (token, _) = tokens.iter().find(|(_, key)| key.decrypt(&frame.payload).is_ok()).unwrap();
+                let kind = if matches!(frame.frame_type, FrameType::Heartbeat) { "heartbeat" } else { "batch" };
+                frames_tx.send((kind, *token)).unwrap();
+            }
+        });
+
+        let config: AgentConfig = toml::from_str(&format!(
+            "[agent]\nserver = \"{}\"\ntoken = \"main\"\n\n[outputs.billing]\ntoken = \"billing\"\n\n[[routes]]\noutput = \"billing\"\nsource = \"docker\"\n",
+            server
+        ))
+        .unwrap();
+        let (tx, rx) = mpsc::channel(10);
+        let handle = tokio::spawn(start(Arc::new(config), rx, Arc::default()));
+        tx.send(test_entry("a", json!({ "source_type": "docker" }))).await.unwrap();
+        tx.send(test_entry("b", json!({ "source_type": "file" }))).await.unwrap();
+
+        // Both batches and the connection's single heartbeat arrive while the
+        // senders are still running
+        let mut received = vec![];
+        while received.len() < 3 {
+            received.push(frames.recv().await.unwrap());
+        }
+        drop(tx);
+        handle.await.unwrap().unwrap();
+        while let Some(frame) = frames.recv().await {
+            received.push(frame);
+        }
+        received.sort();
+        assert_eq!(received, [("batch", "billing"), ("batch", "main"), ("heartbeat", "main")]);
+    }
+}
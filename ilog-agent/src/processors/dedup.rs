@@ -0,0 +1,196 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::DedupConfig;
+use crate::tcp_sender::LogEntry;
+use super::{field_value, Processor};
+
+/// Repeats of one message that are being held back
+struct Run {
+    /// Latest repeat, sent with the repeat attributes
+    last: LogEntry,
+    count: u64,
+    first_timestamp: DateTime<Utc>,
+    started: Instant,
+}
+
+/// Last message seen for one value of the key field
+struct Stream {
+    signature: String,
+    seen_at: Instant,
+    run: Option<Run>,
+}
+
+/// Collapses consecutive identical messages: the first one is forwarded as is,
+/// the repeats that follow within `window` become a single entry with
+/// `repeat_count` and the first/last timestamps of the repeats
+pub struct Dedup {
+    key: String,
+    window: Duration,
+    numbers: Option<Regex>,
+    streams: HashMap<String, Stream>,
+}
+
+impl Dedup {
+    pub fn from_config(config: &DedupConfig) -> Result<Self> {
+        if config.window_secs == 0 {
+            anyhow::bail!("dedup window_secs must be positive");
+        }
+        Ok(Self {
+            key: config.key.clone(),
+            window: Duration::from_secs(config.window_secs),
+            numbers: config.normalize_numbers.then(|| Regex::new(r"\d+(?:\.\d+)?").unwrap()),
+            streams: HashMap::new(),
+        })
+    }
+
+    /// What two entries must share to count as repeats
+    fn signature(&self, entry: &LogEntry) -> String {
+        let message = match &self.numbers {
+            Some(numbers) => numbers.replace_all(&entry.message, "#"),
+            None => entry.message.as_str().into(),
+        };
+        format!("{}|{}", entry.severity.number(), message)
+    }
+
+    fn collapsed(run: Run) -> LogEntry {
+        let mut entry = run.last;
+        let last_timestamp = entry.timestamp;
+        entry.timestamp = run.first_timestamp;
+
+        let mut attributes = match entry.attributes.take() {
+            Some(serde_json::Value::Object(map)) => map,
+            _ => serde_json::Map::new(),
+        };
+        attributes.insert("repeat_count".to_string(), run.count.into());
+        attributes.insert("repeat.first_timestamp".to_string(), run.first_timestamp.to_rfc3339().into());
+        attributes.insert("repeat.last_timestamp".to_string(), last_timestamp.to_rfc3339().into());
+        entry.attributes = Some(serde_json::Value::Object(attributes));
+        entry
+    }
+}
+
+impl Processor for Dedup {
+    fn process(&mut self, entry: LogEntry, now: Instant, out: &mut Vec<LogEntry>) {
+        let key = field_value(&entry, &self.key).unwrap_or_default().into_owned();
+        let signature = self.signature(&entry);
+
+        let Some(stream) = self.streams.get_mut(&key) else {
+            self.streams.insert(key, Stream { signature, seen_at: now, run: None });
+            out.push(entry);
+            return;
+        };
+
+        // A repeat after a quiet period is forwarded like a new message
+        let fresh = now.saturating_duration_since(stream.seen_at) <= self.window;
+        stream.seen_at = now;
+
+        if stream.signature == signature && fresh {
+            let run = stream.run.get_or_insert_with(|| Run {
+                last: entry.clone(),
+                count: 0,
+                first_timestamp: entry.timestamp,
+                started: now,
+            });
+            run.count += 1;
+            run.last = entry;
+            return;
+        }
+
+        if let Some(run) = stream.run.take() {
+            out.push(Self::collapsed(run));
+        }
+        stream.signature = signature;
+        out.push(entry);
+    }
+
+    fn flush(&mut self, now: Instant, out: &mut Vec<LogEntry>) {
+        let window = self.window;
+        for stream in self.streams.values_mut() {
+            if stream.run.as_ref().is_some_and(|run| now.saturating_duration_since(run.started) >= window) {
+                out.push(Self::collapsed(stream.run.take().unwrap()));
+            }
+        }
+        self.streams.retain(|_, stream| {
+            stream.run.is_some() || now.saturating_duration_since(stream.seen_at) <= window
+        });
+    }
+
+    fn drain(&mut self, out: &mut Vec<LogEntry>) {
+        for stream in self.streams.values_mut() {
+            if let Some(run) = stream.run.take() {
+                out.push(Self::collapsed(run));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    fn dedup(normalize_numbers: bool) -> Dedup {
+        Dedup::from_config(&DedupConfig {
+            key: "service".to_string(),
+            window_secs: 10,
+            normalize_numbers,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_collapse_consecutive_repeats() {
+        let mut dedup = dedup(false);
+        let start = Instant::now();
+        let mut out = vec![];
+
+        for i in 0..5 {
+            dedup.process(test_entry("connection refused", json!({})), start + Duration::from_millis(i), &mut out);
+        }
+        assert_eq!(out.len(), 1);
+
+        dedup.process(test_entry("recovered", json!({})), start + Duration::from_secs(1), &mut out);
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[1].message, "connection refused");
+        let attributes = out[1].attributes.as_ref().unwrap();
+        assert_eq!(attributes["repeat_count"], 4);
+        assert!(attributes["repeat.first_timestamp"].as_str().unwrap() <= attributes["repeat.last_timestamp"].as_str().unwrap());
+        assert_eq!(out[2].message, "recovered");
+        assert!(out[2].attributes.as_ref().unwrap().get("repeat_count").is_none());
+    }
+
+    #[test]
+    fn test_window_and_normalization() {
+        let mut dedup = dedup(true);
+        let start = Instant::now();
+        let mut out = vec![];
+
+        for i in 0..4u64 {
+            let message = format!("worker {} crashed after {}ms", i, i * 10);
+            dedup.process(test_entry(&message, json!({})), start + Duration::from_secs(i), &mut out);
+        }
+        assert_eq!(out.len(), 1);
+
+        // The run is emitted once the window has passed, even without a new message
+        dedup.flush(start + Duration::from_secs(5), &mut out);
+        assert_eq!(out.len(), 1);
+        dedup.flush(start + Duration::from_secs(11), &mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1].message, "worker 3 crashed after 30ms");
+        assert_eq!(out[1].attributes.as_ref().unwrap()["repeat_count"], 3);
+
+        // Repeats after a quiet period are forwarded again; drain emits held runs
+        out.clear();
+        dedup.process(test_entry("worker 9 crashed after 1ms", json!({})), start + Duration::from_secs(60), &mut out);
+        dedup.process(test_entry("worker 9 crashed after 1ms", json!({})), start + Duration::from_secs(61), &mut out);
+        assert_eq!(out.len(), 1);
+        dedup.drain(&mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[1].attributes.as_ref().unwrap()["repeat_count"], 1);
+    }
+}
@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::time::Instant;
+
+use crate::config::{FilterConfig, FilterRule};
+use crate::tcp_sender::LogEntry;
+use super::{field_value, Processor};
+
+/// A regex matched against one field of an entry
+pub struct Rule {
+    field: String,
+    pattern: Regex,
+}
+
+impl Rule {
+    pub fn from_config(rule: &FilterRule) -> Result<Self> {
+        let pattern = Regex::new(&rule.pattern)
+            .with_context(|| format!("invalid pattern for field '{}'", rule.field))?;
+        Ok(Self {
+            field: rule.field.clone(),
+            pattern,
+        })
+    }
+
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        field_value(entry, &self.field).is_some_and(|value| self.pattern.is_match(&value))
+    }
+}
+
+/// Keeps entries matching any `include` rule (if there are any) and none of the `exclude` rules
+pub struct Filter {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+}
+
+impl Filter {
+    pub fn from_config(config: &FilterConfig) -> Result<Self> {
+        Ok(Self {
+            include: config.include.iter().map(Rule::from_config).collect::<Result<_>>()?,
+            exclude: config.exclude.iter().map(Rule::from_config).collect::<Result<_>>()?,
+        })
+    }
+
+    fn keep(&self, entry: &LogEntry) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|rule| rule.matches(entry)))
+            && !self.exclude.iter().any(|rule| rule.matches(entry))
+    }
+}
+
+impl Processor for Filter {
+    fn process(&mut self, entry: LogEntry, _now: Instant, out: &mut Vec<LogEntry>) {
+        if self.keep(&entry) {
+            out.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    fn rule(field: &str, pattern: &str) -> FilterRule {
+        FilterRule {
+            field: field.to_string(),
+            pattern: pattern.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_include_exclude() {
+        let filter = Filter::from_config(&FilterConfig {
+            include: vec![rule("source_type", "^(file|docker)$")],
+            exclude: vec![rule("message", "(?i)health ?check"), rule("attributes.status", "^2\\d\\d$")],
+        })
+        .unwrap();
+
+        assert!(filter.keep(&test_entry("boom", json!({ "source_type": "file", "status": 500 }))));
+        assert!(!filter.keep(&test_entry("boom", json!({ "source_type": "file", "status": 204 }))));
+        assert!(!filter.keep(&test_entry("GET /healthcheck", json!({ "source_type": "docker" }))));
+        assert!(!filter.keep(&test_entry("boom", json!({ "source_type": "journald" }))));
+
+        assert!(Filter::from_config(&FilterConfig { include: vec![rule("message", "(")], exclude: vec![] }).is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::config::{ProcessorConfig, TraceContextConfig};
+use crate::tcp_sender::LogEntry;
+
+mod dedup;
+mod filter;
+mod rate_limit;
+mod redact;
+mod sample;
+mod trace_context;
+
+pub use filter::Rule;
+
+/// How often stateful processors get a chance to emit pending entries
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A stage between the providers and the sender
+pub trait Processor: Send {
+    /// Push zero or more entries derived from `entry` into `out`
+    fn process(&mut self, entry: LogEntry, now: Instant, out: &mut Vec<LogEntry>);
+
+    /// Periodic hook for entries that are not a direct result of an input (summaries, flushes)
+    fn flush(&mut self, _now: Instant, _out: &mut Vec<LogEntry>) {}
+
+    /// Called once on shutdown, after a last `flush`: emit everything still held back
+    fn drain(&mut self, _out: &mut Vec<LogEntry>) {}
+}
+
+/// Which hooks a pass through the chain calls besides `process`
+#[derive(Clone, Copy, PartialEq)]
+enum Pass {
+    Process,
+    Flush,
+    Drain,
+}
+
+/// Value of a field referenced from processor config: `message`, `service`,
+/// `severity`, `trace_id`, `span_id`, an attribute (`attributes.<key>` or just
+/// `<key>`) or, failing that, a resource attribute (`k8s.pod.name`)
+pub fn field_value<'a>(entry: &'a LogEntry, field: &str) -> Option<Cow<'a, str>> {
+    match field {
+        "message" => Some(Cow::Borrowed(&entry.message)),
+        "service" => Some(Cow::Borrowed(&entry.service)),
+        "severity" => Some(Cow::Borrowed(entry.severity.text())),
+        "trace_id" | "span_id" if entry.trace.is_some() => {
+            let trace = entry.trace.as_ref()?;
+            match field {
+                "trace_id" => Some(Cow::Borrowed(&trace.trace_id)),
+                _ => trace.span_id.as_deref().map(Cow::Borrowed),
+            }
+        }
+        _ => {
+            let key = field.strip_prefix("attributes.").unwrap_or(field);
+            let value = entry
+                .attributes
+                .as_ref()
+                .and_then(|attributes| attributes.get(key))
+                .or_else(|| entry.resource.as_ref()?.get(key))?;
+            match value {
+                serde_json::Value::String(s) => Some(Cow::Borrowed(s)),
+                serde_json::Value::Null => None,
+                other => Some(Cow::Owned(other.to_string())),
+            }
+        }
+    }
+}
+
+/// Processors applied in configuration order
+pub struct ProcessorChain {
+    processors: Vec<Box<dyn Processor>>,
+}
+
+impl ProcessorChain {
+    pub fn from_config(configs: &[ProcessorConfig]) -> Result<Self> {
+        let processors = configs
+            .iter()
+            .enumerate()
+            .map(|(i, config)| {
+                let processor: Result<Box<dyn Processor>> = match config {
+                    ProcessorConfig::Filter(cfg) => filter::Filter::from_config(cfg).map(|p| Box::new(p) as _),
+                    ProcessorConfig::Sample(cfg) => sample::Sampler::from_config(cfg).map(|p| Box::new(p) as _),
+                    ProcessorConfig::RateLimit(cfg) => rate_limit::RateLimiter::from_config(cfg).map(|p| Box::new(p) as _),
+                    ProcessorConfig::Redact(cfg) => redact::Redactor::from_config(cfg).map(|p| Box::new(p) as _),
+                    ProcessorConfig::Dedup(cfg) => dedup::Dedup::from_config(cfg).map(|p| Box::new(p) as _),
+                };
+                processor.with_context(|| format!("processors[{}]", i))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { processors })
+    }
+
+    /// Put trace context extraction in front of the configured processors,
+    /// so they can refer to `trace_id` and `span_id`
+    pub fn with_trace_context(mut self, config: &TraceContextConfig) -> Result<Self> {
+        if config.enabled {
+            let extractor = trace_context::TraceExtractor::from_config(config).context("trace_context")?;
+            self.processors.insert(0, Box::new(extractor));
+        }
+        Ok(self)
+    }
+
+    /// Run entries through every stage; `out` receives what is left
+    fn run_stages(&mut self, input: Vec<LogEntry>, now: Instant, pass: Pass) -> Vec<LogEntry> {
+        let mut current = input;
+        for processor in &mut self.processors {
+            let mut next = Vec::with_capacity(current.len());
+            for entry in current {
+                processor.process(entry, now, &mut next);
+            }
+            if pass != Pass::Process {
+                processor.flush(now, &mut next);
+            }
+            if pass == Pass::Drain {
+                processor.drain(&mut next);
+            }
+            current = next;
+        }
+        current
+    }
+
+    pub fn process(&mut self, entry: LogEntry, now: Instant) -> Vec<LogEntry> {
+        self.run_stages(vec![entry], now, Pass::Process)
+    }
+
+    pub fn flush(&mut self, now: Instant) -> Vec<LogEntry> {
+        self.run_stages(Vec::new(), now, Pass::Flush)
+    }
+
+    pub fn drain(&mut self, now: Instant) -> Vec<LogEntry> {
+        self.run_stages(Vec::new(), now, Pass::Drain)
+    }
+
+    /// Forward entries from the providers to the sender until the providers hang up
+    pub async fn run(mut self, mut rx: mpsc::Receiver<LogEntry>, tx: mpsc::Sender<LogEntry>) {
+        info!("Processor chain running with {} stages", self.processors.len());
+        let mut ticker = tokio::time::interval(FLUSH_INTERVAL);
+
+        loop {
+            let output = tokio::select! {
+                entry = rx.recv() => match entry {
+                    Some(entry) => self.process(entry, Instant::now()),
+                    None => {
+                        for entry in self.drain(Instant::now()) {
+                            let _ = tx.send(entry).await;
+                        }
+                        return;
+                    }
+                },
+                _ = ticker.tick() => self.flush(Instant::now()),
+            };
+
+            for entry in output {
+                if tx.send(entry).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Entry produced by the agent itself, e.g. drop summaries
+pub fn agent_event(severity: crate::severity::Severity, message: String, attributes: serde_json::Value) -> LogEntry {
+    let mut attributes = match attributes {
+        serde_json::Value::Object(map) => map,
+        _ => serde_json::Map::new(),
+    };
+    attributes.insert("source_type".to_string(), "agent".into());
+
+    LogEntry {
+        timestamp: chrono::Utc::now(),
+        observed_timestamp: chrono::Utc::now(),
+        severity,
+        service: "ilog-agent".to_string(),
+        message,
+        body: None,
+        attributes: Some(serde_json::Value::Object(attributes)),
+        trace: None,
+        scope: None,
+        resource: None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_entry(message: &str, attributes: serde_json::Value) -> LogEntry {
+    LogEntry {
+        timestamp: chrono::Utc::now(),
+        observed_timestamp: chrono::Utc::now(),
+        severity: crate::severity::Severity::INFO,
+        service: "app".to_string(),
+        message: message.to_string(),
+        body: None,
+        attributes: Some(attributes),
+        trace: None,
+        scope: None,
+        resource: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FilterConfig, FilterRule, SampleConfig};
+
+    #[test]
+    fn test_field_value() {
+        let entry = test_entry("hello", serde_json::json!({ "status": 200, "path": "/health" }));
+        assert_eq!(field_value(&entry, "message").as_deref(), Some("hello"));
+        assert_eq!(field_value(&entry, "severity").as_deref(), Some("INFO"));
+        assert_eq!(field_value(&entry, "attributes.status").as_deref(), Some("200"));
+        assert_eq!(field_value(&entry, "path").as_deref(), Some("/health"));
+        assert_eq!(field_value(&entry, "missing"), None);
+
+        let mut entry = test_entry("hello", serde_json::json!({ "span_id": "not-moved" }));
+        entry.trace = Some(crate::tcp_sender::TraceContext {
+            trace_id: "4bf92f3577b34da6a3ce929d0e0e4736".to_string(),
+            span_id: None,
+            trace_flags: None,
+        });
+        assert_eq!(field_value(&entry, "trace_id").as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+        assert_eq!(field_value(&entry, "span_id"), None);
+
+        entry.resource = Some(serde_json::Map::from_iter([("k8s.pod.name".to_string(), "api-0".into())]));
+        assert_eq!(field_value(&entry, "k8s.pod.name").as_deref(), Some("api-0"));
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let configs = vec![
+            ProcessorConfig::Filter(FilterConfig {
+                include: vec![],
+                exclude: vec![FilterRule { field: "path".to_string(), pattern: "^/health$".to_string() }],
+            }),
+            ProcessorConfig::Sample(SampleConfig { rate: 1.0, key: None }),
+        ];
+        let mut chain = ProcessorChain::from_config(&configs).unwrap();
+        let now = Instant::now();
+
+        assert!(chain.process(test_entry("GET", serde_json::json!({ "path": "/health" })), now).is_empty());
+        assert_eq!(chain.process(test_entry("GET", serde_json::json!({ "path": "/api" })), now).len(), 1);
+
+        let invalid = vec![ProcessorConfig::Sample(SampleConfig { rate: 2.0, key: None })];
+        let error = ProcessorChain::from_config(&invalid).err().unwrap();
+        assert!(format!("{:#}", error).starts_with("processors[0]"));
+    }
+}
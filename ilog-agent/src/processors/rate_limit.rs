@@ -0,0 +1,172 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::config::RateLimitConfig;
+use crate::severity::Severity;
+use crate::tcp_sender::LogEntry;
+use super::{agent_event, field_value, Processor};
+
+/// How often a "N entries dropped" summary is emitted per key
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(10);
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    dropped: u64,
+    summarized_at: Instant,
+}
+
+/// Token bucket per value of the `key` field (the source type by default)
+pub struct RateLimiter {
+    key: String,
+    rate: f64,
+    burst: f64,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn from_config(config: &RateLimitConfig) -> Result<Self> {
+        if config.rate <= 0.0 {
+            anyhow::bail!("rate limit must be positive, got {}", config.rate);
+        }
+        Ok(Self {
+            key: config.key.clone(),
+            rate: config.rate,
+            burst: config.burst.unwrap_or(config.rate).max(1.0),
+            buckets: HashMap::new(),
+        })
+    }
+
+    fn summary(&self, key: &str, dropped: u64) -> LogEntry {
+        agent_event(
+            Severity::WARN,
+            format!("{} entries dropped by rate limit ({}={}, {}/s)", dropped, self.key, key, self.rate),
+            serde_json::json!({
+                "ilog.dropped_count": dropped,
+                "ilog.rate_limit.key": key,
+            }),
+        )
+    }
+}
+
+impl Processor for RateLimiter {
+    fn process(&mut self, entry: LogEntry, now: Instant, out: &mut Vec<LogEntry>) {
+        let key = field_value(&entry, &self.key).unwrap_or_default().into_owned();
+        let bucket = self.buckets.entry(key).or_insert_with(|| Bucket {
+            tokens: self.burst,
+            refilled_at: now,
+            dropped: 0,
+            summarized_at: now,
+        });
+
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            out.push(entry);
+        } else {
+            bucket.dropped += 1;
+        }
+    }
+
+    fn flush(&mut self, now: Instant, out: &mut Vec<LogEntry>) {
+        let mut summaries = Vec::new();
+        for (key, bucket) in &mut self.buckets {
+            if bucket.dropped > 0 && now.saturating_duration_since(bucket.summarized_at) >= SUMMARY_INTERVAL {
+                summaries.push((key.clone(), bucket.dropped));
+                bucket.dropped = 0;
+                bucket.summarized_at = now;
+            }
+        }
+        out.extend(summaries.into_iter().map(|(key, dropped)| self.summary(&key, dropped)));
+
+        // Forget sources that have been quiet long enough to have a full bucket again
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            bucket.dropped > 0 || now.saturating_duration_since(bucket.refilled_at).as_secs_f64() * rate < burst
+        });
+    }
+
+    /// Report drops that have not been summarized yet, however recent
+    fn drain(&mut self, out: &mut Vec<LogEntry>) {
+        let mut summaries: Vec<_> = self
+            .buckets
+            .drain()
+            .filter(|(_, bucket)| bucket.dropped > 0)
+            .map(|(key, bucket)| (key, bucket.dropped))
+            .collect();
+        summaries.sort();
+        out.extend(summaries.into_iter().map(|(key, dropped)| self.summary(&key, dropped)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    #[test]
+    fn test_token_bucket_and_summary() {
+        let mut limiter = RateLimiter::from_config(&RateLimitConfig {
+            key: "source_type".to_string(),
+            rate: 2.0,
+            burst: Some(3.0),
+        })
+        .unwrap();
+        let start = Instant::now();
+        let mut out = vec![];
+
+        for _ in 0..5 {
+            limiter.process(test_entry("chatty", json!({ "source_type": "docker" })), start, &mut out);
+        }
+        limiter.process(test_entry("quiet", json!({ "source_type": "file" })), start, &mut out);
+        assert_eq!(out.len(), 4);
+
+        // One second later two more tokens are available
+        out.clear();
+        for _ in 0..3 {
+            limiter.process(test_entry("chatty", json!({ "source_type": "docker" })), start + Duration::from_secs(1), &mut out);
+        }
+        assert_eq!(out.len(), 2);
+
+        out.clear();
+        limiter.flush(start + Duration::from_secs(5), &mut out);
+        assert!(out.is_empty());
+        limiter.flush(start + SUMMARY_INTERVAL, &mut out);
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].message, "3 entries dropped by rate limit (source_type=docker, 2/s)");
+        assert_eq!(out[0].attributes.as_ref().unwrap()["ilog.dropped_count"], 3);
+        assert_eq!(out[0].severity, Severity::WARN);
+    }
+
+    #[test]
+    fn test_drain_reports_pending_drops() {
+        let mut limiter = RateLimiter::from_config(&RateLimitConfig {
+            key: "source_type".to_string(),
+            rate: 1.0,
+            burst: None,
+        })
+        .unwrap();
+        let start = Instant::now();
+        let mut out = vec![];
+
+        for source in ["file", "docker", "docker", "file", "file", "exec"] {
+            limiter.process(test_entry("line", json!({ "source_type": source })), start, &mut out);
+        }
+        assert_eq!(out.len(), 3);
+
+        // Shutting down before the summary interval still reports every drop
+        out.clear();
+        limiter.flush(start, &mut out);
+        limiter.drain(&mut out);
+        let messages: Vec<_> = out.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(messages, [
+            "1 entries dropped by rate limit (source_type=docker, 1/s)",
+            "2 entries dropped by rate limit (source_type=file, 1/s)",
+        ]);
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use std::time::Instant;
+
+use crate::config::SampleConfig;
+use crate::tcp_sender::LogEntry;
+use super::{field_value, Processor};
+
+/// FNV-1a, stable across agents and versions so every host keeps the same keys
+fn fnv1a(value: &str) -> u64 {
+    value.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Keeps a fraction of entries, either at random or by hashing a key field
+/// (so all entries sharing e.g. a trace id are kept or dropped together)
+pub struct Sampler {
+    rate: f64,
+    key: Option<String>,
+}
+
+impl Sampler {
+    pub fn from_config(config: &SampleConfig) -> Result<Self> {
+        if !(0.0..=1.0).contains(&config.rate) {
+            anyhow::bail!("sample rate must be between 0 and 1, got {}", config.rate);
+        }
+        Ok(Self {
+            rate: config.rate,
+            key: config.key.clone(),
+        })
+    }
+
+    fn keep(&self, entry: &LogEntry) -> bool {
+        let key = self.key.as_deref().and_then(|field| field_value(entry, field));
+        match key {
+            Some(key) => (fnv1a(&key) as f64 / u64::MAX as f64) < self.rate,
+            // Entries without the key fall back to random sampling
+            None => rand::random::<f64>() < self.rate,
+        }
+    }
+}
+
+impl Processor for Sampler {
+    fn process(&mut self, entry: LogEntry, _now: Instant, out: &mut Vec<LogEntry>) {
+        if self.keep(&entry) {
+            out.push(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    #[test]
+    fn test_hash_sampling_is_deterministic() {
+        let sampler = Sampler::from_config(&SampleConfig { rate: 0.5, key: Some("trace_id".to_string()) }).unwrap();
+        let kept = (0..1000)
+            .filter(|i| {
+                let entry = test_entry("x", json!({ "trace_id": format!("trace-{}", i) }));
+                let first = sampler.keep(&entry);
+                assert_eq!(first, sampler.keep(&entry));
+                first
+            })
+            .count();
+        assert!((400..600).contains(&kept), "kept {}", kept);
+    }
+
+    #[test]
+    fn test_rate_bounds() {
+        let none = Sampler::from_config(&SampleConfig { rate: 0.0, key: None }).unwrap();
+        let all = Sampler::from_config(&SampleConfig { rate: 1.0, key: None }).unwrap();
+        let entry = test_entry("x", json!({}));
+        assert!((0..100).all(|_| !none.keep(&entry) && all.keep(&entry)));
+        assert!(Sampler::from_config(&SampleConfig { rate: -0.1, key: None }).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use anyhow::Result;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::time::Instant;
+
+use crate::config::TraceContextConfig;
+use crate::tcp_sender::{LogEntry, TraceContext};
+use super::Processor;
+
+/// W3C `traceparent`: version, trace id, parent (span) id and flags
+const TRACEPARENT: &str = r"\b([0-9a-f]{2})-([0-9a-f]{32})-([0-9a-f]{16})-([0-9a-f]{2})\b";
+
+/// Lowercase id of exactly `len` hex digits; all zeros means "no id"
+fn valid_id(value: &Value, len: usize) -> Option<String> {
+    let id = value.as_str()?.trim().to_ascii_lowercase();
+    (id.len() == len && id.bytes().all(|b| b.is_ascii_hexdigit()) && id.bytes().any(|b| b != b'0')).then_some(id)
+}
+
+/// Flags as a number, a hex string (`"01"`) or a sampled boolean
+fn valid_flags(value: &Value) -> Option<u8> {
+    match value {
+        Value::Number(n) => n.as_u64().and_then(|n| u8::try_from(n).ok()),
+        Value::Bool(sampled) => Some(u8::from(*sampled)),
+        Value::String(s) => match s.trim() {
+            "true" => Some(1),
+            "false" => Some(0),
+            hex => u8::from_str_radix(hex, 16).ok(),
+        },
+        _ => None,
+    }
+}
+
+/// First of `names` present in `fields` with a value `parse` accepts
+fn lookup<T>(fields: &Map<String, Value>, names: &[String], parse: impl Fn(&Value) -> Option<T>) -> Option<(String, T)> {
+    names
+        .iter()
+        .find_map(|name| fields.get(name).and_then(&parse).map(|value| (name.clone(), value)))
+}
+
+/// Moves trace and span ids from attributes, JSON messages or a `traceparent`
+/// into the entry's trace context
+pub struct TraceExtractor {
+    config: TraceContextConfig,
+    traceparent: Regex,
+}
+
+impl TraceExtractor {
+    pub fn from_config(config: &TraceContextConfig) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            traceparent: Regex::new(TRACEPARENT)?,
+        })
+    }
+
+    fn parse_traceparent(&self, text: &str) -> Option<TraceContext> {
+        let captures = self.traceparent.captures(text)?;
+        if &captures[1] == "ff" {
+            return None;
+        }
+        Some(TraceContext {
+            trace_id: valid_id(&captures[2].into(), 32)?,
+            span_id: valid_id(&captures[3].into(), 16),
+            trace_flags: u8::from_str_radix(&captures[4], 16).ok(),
+        })
+    }
+
+    /// Trace context from record fields, with the keys it was read from
+    fn in_fields(&self, fields: &Map<String, Value>) -> Option<(TraceContext, Vec<String>)> {
+        if let Some((key, trace_id)) = lookup(fields, &self.config.trace_id_fields, |v| valid_id(v, 32)) {
+            let mut keys = vec![key];
+            let span_id = lookup(fields, &self.config.span_id_fields, |v| valid_id(v, 16));
+            let trace_flags = lookup(fields, &self.config.trace_flags_fields, valid_flags);
+            let context = TraceContext {
+                trace_id,
+                span_id: span_id.map(|(key, id)| {
+                    keys.push(key);
+                    id
+                }),
+                trace_flags: trace_flags.map(|(key, flags)| {
+                    keys.push(key);
+                    flags
+                }),
+            };
+            return Some((context, keys));
+        }
+
+        lookup(fields, &self.config.traceparent_fields, |v| self.parse_traceparent(v.as_str()?))
+            .map(|(key, context)| (context, vec![key]))
+    }
+
+    fn extract(&self, entry: &mut LogEntry) -> Option<TraceContext> {
+        if let Some(Value::Object(attributes)) = entry.attributes.as_mut() {
+            if let Some((context, keys)) = self.in_fields(attributes) {
+                for key in keys {
+                    attributes.remove(&key);
+                }
+                return Some(context);
+            }
+        }
+
+        if entry.message.trim_start().starts_with('{') {
+            if let Ok(Value::Object(record)) = serde_json::from_str(&entry.message) {
+                if let Some((context, _)) = self.in_fields(&record) {
+                    return Some(context);
+                }
+            }
+        }
+
+        if self.config.message_traceparent {
+            return self.parse_traceparent(&entry.message);
+        }
+        None
+    }
+}
+
+impl Processor for TraceExtractor {
+    fn process(&mut self, mut entry: LogEntry, _now: Instant, out: &mut Vec<LogEntry>) {
+        // Providers with native trace context (OTLP) have already set it
+        if entry.trace.is_none() {
+            entry.trace = self.extract(&mut entry);
+        }
+        out.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::test_entry;
+    use serde_json::json;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+
+    fn extract(entry: LogEntry) -> LogEntry {
+        let mut extractor = TraceExtractor::from_config(&TraceContextConfig::default()).unwrap();
+        let mut out = vec![];
+        extractor.process(entry, Instant::now(), &mut out);
+        out.pop().unwrap()
+    }
+
+    #[test]
+    fn test_from_attributes() {
+        let entry = extract(test_entry(
+            "request done",
+            json!({ "traceId": TRACE_ID.to_uppercase(), "span_id": SPAN_ID, "trace_flags": "01", "path": "/" }),
+        ));
+        let trace = entry.trace.unwrap();
+        assert_eq!(trace.trace_id, TRACE_ID);
+        assert_eq!(trace.span_id.as_deref(), Some(SPAN_ID));
+        assert_eq!(trace.trace_flags, Some(1));
+        // The ids moved out of the attributes
+        assert_eq!(entry.attributes, Some(json!({ "path": "/" })));
+
+        // Malformed and all-zero ids are ignored and left alone
+        let entry = extract(test_entry("x", json!({ "trace_id": "abc", "trace.id": "0".repeat(32) })));
+        assert!(entry.trace.is_none());
+        assert_eq!(entry.attributes.unwrap()["trace_id"], "abc");
+    }
+
+    #[test]
+    fn test_from_message() {
+        let message = format!(r#"{{"msg": "charged", "trace_id": "{}", "otelTraceSampled": true}}"#, TRACE_ID);
+        let trace = extract(test_entry(&message, json!({}))).trace.unwrap();
+        assert_eq!(trace.trace_id, TRACE_ID);
+        assert_eq!(trace.span_id, None);
+        assert_eq!(trace.trace_flags, Some(1));
+
+        let message = format!("GET /checkout traceparent=00-{}-{}-00 200", TRACE_ID, SPAN_ID);
+        let trace = extract(test_entry(&message, json!({}))).trace.unwrap();
+        assert_eq!((trace.trace_id.as_str(), trace.span_id.as_deref(), trace.trace_flags), (TRACE_ID, Some(SPAN_ID), Some(0)));
+
+        let traceparent = format!("00-{}-{}-01", TRACE_ID, SPAN_ID);
+        let entry = extract(test_entry("proxied", json!({ "traceparent": traceparent })));
+        assert_eq!(entry.trace.unwrap().trace_flags, Some(1));
+
+        assert!(extract(test_entry(&format!("ff-{}-{}-01", TRACE_ID, SPAN_ID), json!({}))).trace.is_none());
+    }
+}
@@ -1,6 +1,5 @@
 use anyhow::{Context, Result};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 const MAGIC_BYTES: &[u8; 4] = b"ILOG";
 const VERSION: u8 = 1;
@@ -47,11 +46,17 @@ impl Frame {
         Self::new(FrameType::Heartbeat, Vec::new())
     }
 
+    /// Heartbeat carrying the agent's encoded health report
+    pub fn heartbeat_with(payload: Vec<u8>) -> Self {
+        Self::new(FrameType::Heartbeat, payload)
+    }
+
+    #[cfg(test)]
     pub fn ack() -> Self {
         Self::new(FrameType::Ack, Vec::new())
     }
 
-    pub async fn write_to(&self, stream: &mut TcpStream) -> Result<()> {
+    pub async fn write_to<W: AsyncWrite + Unpin>(&self, stream: &mut W) -> Result<()> {
         stream.write_all(MAGIC_BYTES).await?;
         stream.write_u8(VERSION).await?;
         stream.write_u8(self.frame_type as u8).await?;
@@ -61,7 +66,7 @@ impl Frame {
         Ok(())
     }
 
-    pub async fn read_from(stream: &mut TcpStream) -> Result<Self> {
+    pub async fn read_from<R: AsyncRead + Unpin>(stream: &mut R) -> Result<Self> {
         let mut magic = [0u8; 4];
         stream
             .read_exact(&mut magic)
@@ -108,7 +113,6 @@ impl Frame {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio::io::DuplexStream;
 
     #[tokio::test]
     async fn test_frame_roundtrip() {
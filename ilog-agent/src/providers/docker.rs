@@ -1,24 +1,34 @@
 use tokio::sync::mpsc;
+use tokio::task::{AbortHandle, JoinSet};
 use anyhow::{Result, Context};
 use async_trait::async_trait;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use bollard::{Docker, container::LogsOptions};
-use bollard::container::LogOutput;
+use bollard::container::{ListContainersOptions, LogOutput};
+use bollard::system::EventsOptions;
 use futures::StreamExt;
+use chrono::{DateTime, Utc};
 use tracing::{info, error, warn};
-use regex::Regex;
 
-use crate::config::AgentConfig;
+use crate::config::{AgentConfig, DockerSource};
+use crate::service_name::ServiceNameTemplate;
+use crate::severity::{Severity, SeverityMapper};
+use crate::state::StateStore;
 use crate::tcp_sender::LogEntry;
 use super::LogProvider;
 
 pub struct DockerProvider {
     config: Arc<AgentConfig>,
+    state: Arc<StateStore>,
 }
 
 impl DockerProvider {
     pub fn new(config: Arc<AgentConfig>) -> Self {
-        Self { config }
+        let state = StateStore::open(&config.agent.state_dir, "docker");
+        Self { config, state }
     }
 }
 
@@ -46,47 +56,6 @@ fn strip_ansi_codes(text: &str) -> String {
     result
 }
 
-/// Parse log level from message and return cleaned message
-fn parse_log_level_and_clean(message: &str) -> (Option<String>, String) {
-    let upper = message.to_uppercase();
-    
-    let patterns = [
-        ("ERROR", "ERROR"),
-        ("ERR", "ERROR"),
-        ("WARN", "WARN"),
-        ("WARNING", "WARN"),
-        ("INFO", "INFO"),
-        ("DEBUG", "DEBUG"),
-    ];
-    
-    for (pattern, level) in patterns.iter() {
-        let pattern_variations = vec![
-            format!(" {} ", pattern),
-            format!("[{}]", pattern),
-            format!("({})", pattern),
-            format!("{} ", pattern),
-            format!(" {}", pattern),
-        ];
-        
-        for var in pattern_variations {
-            if upper.contains(&var.to_uppercase()) {
-                let mut cleaned = message.to_string();
-                cleaned = cleaned.replace(&var, " ");
-                cleaned = cleaned.replace(&var.to_lowercase(), " ");
-                cleaned = cleaned.replace(&var.to_uppercase(), " ");
-                
-                while cleaned.contains("  ") {
-                    cleaned = cleaned.replace("  ", " ");
-                }
-                
-                return (Some(level.to_string()), cleaned.trim().to_string());
-            }
-        }
-    }
-    
-    (None, message.to_string())
-}
-
 /// Parse timestamp from message and return cleaned message
 fn parse_timestamp_and_clean(message: &str) -> (Option<chrono::DateTime<chrono::Utc>>, String) {
     use chrono::DateTime;
@@ -121,136 +90,484 @@ fn parse_timestamp_and_clean(message: &str) -> (Option<chrono::DateTime<chrono::
     (None, message.to_string())
 }
 
-async fn watch_container(
+/// Decides which containers the provider attaches to
+struct ContainerSelector {
+    all: bool,
+    names: Vec<glob::Pattern>,
+    exclude_names: Vec<glob::Pattern>,
+    include_labels: Vec<(String, Option<String>)>,
+}
+
+impl ContainerSelector {
+    fn from_config(config: &DockerSource) -> Result<Self> {
+        let compile = |patterns: &[String], key: &str| -> Result<Vec<glob::Pattern>> {
+            patterns
+                .iter()
+                .map(|p| {
+                    glob::Pattern::new(p)
+                        .with_context(|| format!("sources.docker.{}: invalid pattern '{}'", key, p))
+                })
+                .collect()
+        };
+
+        let include_labels = config
+            .include_labels
+            .iter()
+            .map(|selector| match selector.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.to_string())),
+                None => (selector.clone(), None),
+            })
+            .collect();
+
+        Ok(Self {
+            all: config.all,
+            names: compile(&config.containers, "containers")?,
+            exclude_names: compile(&config.exclude_names, "exclude_names")?,
+            include_labels,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.all && self.names.is_empty() && self.include_labels.is_empty()
+    }
+
+    fn matches(&self, name: &str, labels: &HashMap<String, String>) -> bool {
+        if self.exclude_names.iter().any(|p| p.matches(name)) {
+            return false;
+        }
+
+        self.all
+            || self.names.iter().any(|p| p.matches(name))
+            || self.include_labels.iter().any(|(key, value)| match (labels.get(key), value) {
+                (Some(actual), Some(expected)) => actual == expected,
+                (Some(_), None) => true,
+                (None, _) => false,
+            })
+    }
+}
+
+/// Container details attached to every entry, fetched once per container id
+struct ContainerMetadata {
+    id: String,
+    name: String,
+    /// From the `service_name` template, the container name by default
+    service: String,
+    image: Option<String>,
+    image_tag: Option<String>,
+    compose_project: Option<String>,
+    compose_service: Option<String>,
+    labels: Vec<(String, String)>,
+}
+
+impl ContainerMetadata {
+    /// OpenTelemetry resource attributes identifying the container
+    fn resource(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut resource = serde_json::Map::new();
+        let mut insert = |key: &str, value: &str| {
+            resource.insert(key.to_string(), serde_json::Value::String(value.to_string()));
+        };
+
+        insert("container.id", &self.id);
+        insert("container.name", &self.name);
+        if let Some(image) = &self.image {
+            insert("container.image.name", image);
+        }
+        if let Some(tag) = &self.image_tag {
+            insert("container.image.tag", tag);
+        }
+        if let Some(project) = &self.compose_project {
+            insert("docker.compose.project", project);
+        }
+        if let Some(service) = &self.compose_service {
+            insert("docker.compose.service", service);
+        }
+        for (key, value) in &self.labels {
+            insert(&format!("container.label.{}", key), value);
+        }
+
+        resource
+    }
+
+    fn attributes(&self, stream: &str) -> serde_json::Value {
+        serde_json::json!({
+            "source_type": "docker",
+            "container": self.name,
+            "log.iostream": stream,
+        })
+    }
+}
+
+/// Resolve the `service_name` template against a container and all of its labels
+fn container_service(
+    template: &ServiceNameTemplate,
+    metadata: &ContainerMetadata,
+    labels: &HashMap<String, String>,
+) -> Option<String> {
+    template.render(|placeholder| match placeholder {
+        "name" => Some(Cow::Borrowed(&metadata.name)),
+        "image" => metadata.image.as_deref().map(Cow::Borrowed),
+        "compose.project" => metadata.compose_project.as_deref().map(Cow::Borrowed),
+        "compose.service" => metadata.compose_service.as_deref().map(Cow::Borrowed),
+        _ => placeholder
+            .strip_prefix("label:")
+            .and_then(|key| labels.get(key))
+            .map(|value| Cow::Borrowed(value.as_str())),
+    })
+}
+
+/// Split an image reference into name and tag ("registry:5000/app:v1" -> "registry:5000/app", "v1")
+fn split_image(image: &str) -> (String, Option<String>) {
+    let image = image.split('@').next().unwrap_or(image);
+    let name_start = image.rfind('/').map(|i| i + 1).unwrap_or(0);
+    match image[name_start..].rfind(':') {
+        Some(i) => (
+            image[..name_start + i].to_string(),
+            Some(image[name_start + i + 1..].to_string()),
+        ),
+        None => (image.to_string(), None),
+    }
+}
+
+type MetadataCache = Arc<std::sync::Mutex<HashMap<String, Arc<ContainerMetadata>>>>;
+
+/// Everything a container log stream needs, shared by all streams
+#[derive(Clone)]
+struct WatchContext {
     docker: Docker,
-    container_name: String,
+    severity: Arc<SeverityMapper>,
+    stderr_severity: Severity,
+    labels: Arc<Vec<String>>,
+    service_name: Option<Arc<ServiceNameTemplate>>,
+    metadata: MetadataCache,
+    state: Arc<StateStore>,
     tx: mpsc::Sender<LogEntry>,
+}
+
+impl WatchContext {
+    /// Cached `inspect_container` result; falls back to bare id/name if inspection fails
+    async fn metadata(&self, id: &str, name: &str) -> Arc<ContainerMetadata> {
+        if let Some(metadata) = self.metadata.lock().unwrap().get(id) {
+            return metadata.clone();
+        }
+
+        let inspect = match self.docker.inspect_container(id, None).await {
+            Ok(inspect) => inspect,
+            Err(e) => {
+                warn!("Failed to inspect container {}: {}", name, e);
+                return Arc::new(ContainerMetadata {
+                    id: id.to_string(),
+                    name: name.to_string(),
+                    service: name.to_string(),
+                    image: None,
+                    image_tag: None,
+                    compose_project: None,
+                    compose_service: None,
+                    labels: Vec::new(),
+                });
+            }
+        };
+
+        let config = inspect.config.unwrap_or_default();
+        let all_labels = config.labels.unwrap_or_default();
+        let (image, image_tag) = match config.image.as_deref().map(split_image) {
+            Some((image, tag)) => (Some(image), tag),
+            None => (None, None),
+        };
+
+        let name = inspect
+            .name
+            .map(|n| n.trim_start_matches('/').to_string())
+            .unwrap_or_else(|| name.to_string());
+        let mut metadata = ContainerMetadata {
+            id: id.to_string(),
+            service: name.clone(),
+            name,
+            image,
+            image_tag,
+            compose_project: all_labels.get("com.docker.compose.project").cloned(),
+            compose_service: all_labels.get("com.docker.compose.service").cloned(),
+            labels: self
+                .labels
+                .iter()
+                .filter_map(|key| all_labels.get(key).map(|v| (key.clone(), v.clone())))
+                .collect(),
+        };
+        if let Some(template) = &self.service_name {
+            match container_service(template, &metadata, &all_labels) {
+                Some(service) => metadata.service = service,
+                None => warn!("Service name template does not resolve for container {}, using its name", metadata.name),
+            }
+        }
+        let metadata = Arc::new(metadata);
+
+        self.metadata.lock().unwrap().insert(id.to_string(), metadata.clone());
+        metadata
+    }
+}
+
+/// Running log streams keyed by container id. The tasks are owned by the
+/// provider's future, so stopping the provider ends them too.
+struct Attachments {
+    context: WatchContext,
+    tasks: JoinSet<()>,
+    watched: HashMap<String, AbortHandle>,
+}
+
+impl Attachments {
+    /// Start streaming a container unless it is already being watched.
+    /// `since` is a unix timestamp; `None` replays the last few lines instead.
+    fn attach(&mut self, id: &str, name: &str, since: Option<i64>) {
+        while self.tasks.try_join_next().is_some() {}
+        self.watched.retain(|_, handle| !handle.is_finished());
+        if self.watched.contains_key(id) {
+            return;
+        }
+
+        let context = self.context.clone();
+        let container_id = id.to_string();
+        let container_name = name.to_string();
+
+        let handle = self.tasks.spawn(async move {
+            if let Err(e) = watch_container(context, container_id, container_name.clone(), since).await {
+                error!("Error watching container {}: {}", container_name, e);
+            }
+        });
+
+        self.watched.insert(id.to_string(), handle);
+    }
+
+    fn detach(&mut self, id: &str) {
+        if let Some(handle) = self.watched.remove(id) {
+            handle.abort();
+        }
+    }
+
+    /// Detach and drop cached metadata and the resume point once the container is gone
+    fn remove(&mut self, id: &str) {
+        self.detach(id);
+        self.context.metadata.lock().unwrap().remove(id);
+        self.context.state.remove(id);
+    }
+}
+
+/// Running containers accepted by the selector, as (id, name)
+async fn matching_containers(docker: &Docker, selector: &ContainerSelector) -> Result<Vec<(String, String)>> {
+    let containers = docker
+        .list_containers(Some(ListContainersOptions::<String>::default()))
+        .await
+        .context("Failed to list containers")?;
+
+    Ok(containers
+        .into_iter()
+        .filter_map(|container| {
+            let id = container.id?;
+            let name = container
+                .names
+                .as_ref()
+                .and_then(|names| names.first())
+                .map(|n| n.trim_start_matches('/').to_string())
+                .unwrap_or_else(|| id.clone());
+            let labels = container.labels.unwrap_or_default();
+            selector.matches(&name, &labels).then_some((id, name))
+        })
+        .collect())
+}
+
+/// Attach to every running container accepted by the selector
+async fn attach_running(
+    docker: &Docker,
+    selector: &ContainerSelector,
+    attachments: &mut Attachments,
+) -> Result<()> {
+    for (id, name) in matching_containers(docker, selector).await? {
+        attachments.attach(&id, &name, None);
+    }
+    Ok(())
+}
+
+type PendingEntry = (DateTime<Utc>, Severity, String);
+
+/// Multiline reassembly state for one output stream (stdout or stderr)
+#[derive(Default)]
+struct PendingLog {
+    entry: Option<PendingEntry>,
+    /// Docker timestamp of the first line held in `entry`
+    started_at: Option<DateTime<Utc>>,
+    bracket_depth: i32,
+}
+
+impl PendingLog {
+    /// Feed one cleaned line and return the entries it completes.
+    /// `received` is Docker's timestamp for the line, used when the message has none.
+    fn push(
+        &mut self,
+        line: &str,
+        received: DateTime<Utc>,
+        mapper: &SeverityMapper,
+        default_severity: Severity,
+    ) -> Vec<PendingEntry> {
+        let mut complete = Vec::new();
+        let (parsed_timestamp, cleaned_message) = parse_timestamp_and_clean(line);
+
+        for ch in line.chars() {
+            match ch {
+                '{' => self.bracket_depth += 1,
+                '}' => self.bracket_depth = self.bracket_depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+
+        if let Some(ts) = parsed_timestamp {
+            // New entry; only close the pending one if its JSON body is complete
+            if self.bracket_depth == 0 {
+                complete.extend(self.entry.take());
+            }
+
+            let (parsed_level, final_message) = mapper.detect_and_clean(&cleaned_message);
+            if let Some((_, _, ref mut msg)) = self.entry {
+                msg.push('\n');
+                msg.push_str(&final_message);
+            } else {
+                self.entry = Some((ts, parsed_level.unwrap_or(default_severity), final_message));
+                self.started_at = Some(received);
+            }
+        } else if let Some((_, _, ref mut msg)) = self.entry {
+            // Continuation line
+            msg.push('\n');
+            msg.push_str(line);
+            if self.bracket_depth == 0 {
+                complete.extend(self.entry.take());
+            }
+        } else {
+            let (parsed_level, final_message) = mapper.detect_and_clean(line);
+            complete.push((received, parsed_level.unwrap_or(default_severity), final_message));
+        }
+
+        complete
+    }
+
+    /// Latest Docker timestamp up to which every line has been delivered
+    fn resume_point(&self, current: DateTime<Utc>) -> DateTime<Utc> {
+        match (&self.entry, self.started_at) {
+            (Some(_), Some(started_at)) => started_at - chrono::Duration::nanoseconds(1),
+            _ => current,
+        }
+    }
+}
+
+/// Split the RFC 3339 timestamp Docker prepends when `timestamps` is requested
+fn split_docker_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    if let Some((prefix, rest)) = line.split_once(' ') {
+        if let Ok(ts) = DateTime::parse_from_rfc3339(prefix) {
+            return (Some(ts.with_timezone(&Utc)), rest);
+        }
+    }
+    (None, line)
+}
+
+fn format_checkpoint(ts: DateTime<Utc>) -> String {
+    ts.to_rfc3339_opts(chrono::SecondsFormat::Nanos, true)
+}
+
+async fn watch_container(
+    context: WatchContext,
+    container_id: String,
+    container_name: String,
+    since: Option<i64>,
 ) -> Result<()> {
     info!("Starting to watch container: {}", container_name);
+
+    let metadata = context.metadata(&container_id, &container_name).await;
+    let resource = metadata.resource();
+    let stdout_attributes = metadata.attributes("stdout");
+    let stderr_attributes = metadata.attributes("stderr");
+
+    // Resume after the last delivered line if we have seen this container before.
+    // `since` only has second precision, so older lines in that second are skipped below.
+    let checkpoint = context
+        .state
+        .get(&container_id)
+        .and_then(|ts| DateTime::parse_from_rfc3339(&ts).ok())
+        .map(|ts| ts.with_timezone(&Utc));
+    let since = checkpoint.map(|ts| ts.timestamp()).or(since);
+    if let Some(ts) = checkpoint {
+        info!("Resuming container {} after {}", container_name, format_checkpoint(ts));
+    }
     
     let options = LogsOptions::<String> {
         follow: true,
         stdout: true,
         stderr: true,
-        tail: "10".to_string(),
+        timestamps: true,
+        since: since.unwrap_or(0),
+        tail: if since.is_some() { "all" } else { "10" }.to_string(),
         ..Default::default()
     };
 
-    let mut stream = docker.logs(&container_name, Some(options));
+    let mut stream = context.docker.logs(&container_id, Some(options));
     
-    let mut pending_log: Option<(chrono::DateTime<chrono::Utc>, String, String, String)> = None;
-    let mut bracket_depth: i32 = 0;
+    let mut stdout = PendingLog::default();
+    let mut stderr = PendingLog::default();
+    let mut last_seen = None;
 
     while let Some(log_result) = stream.next().await {
         match log_result {
             Ok(log_output) => {
-                let log_text = match log_output {
-                    LogOutput::StdOut { message } => {
-                        String::from_utf8_lossy(&message).to_string()
-                    }
-                    LogOutput::StdErr { message } => {
-                        String::from_utf8_lossy(&message).to_string()
-                    }
+                let (message, is_stderr) = match log_output {
+                    LogOutput::StdOut { message } => (message, false),
+                    LogOutput::StdErr { message } => (message, true),
                     _ => continue,
                 };
+                let raw_text = String::from_utf8_lossy(&message);
+                let (docker_ts, log_text) = split_docker_timestamp(&raw_text);
+
+                if let (Some(ts), Some(checkpoint)) = (docker_ts, checkpoint) {
+                    if ts <= checkpoint {
+                        continue;
+                    }
+                }
 
                 info!("Raw log from {}: {}", container_name, log_text.trim());
 
-                let log_text = strip_ansi_codes(&log_text);
+                let log_text = strip_ansi_codes(log_text);
                 let log_text = log_text.trim();
                 
                 if log_text.is_empty() {
                     continue;
                 }
 
-                let (parsed_timestamp, cleaned_message) = parse_timestamp_and_clean(&log_text);
-                
-                for ch in log_text.chars() {
-                    match ch {
-                        '{' => bracket_depth += 1,
-                        '}' => bracket_depth = bracket_depth.saturating_sub(1),
-                        _ => {}
-                    }
-                }
-                
-                if let Some(ts) = parsed_timestamp {
-                    if bracket_depth == 0 {
-                        if let Some((pending_ts, pending_level, pending_service, pending_msg)) = pending_log.take() {
-                            let mut attributes = serde_json::Map::new();
-                            attributes.insert("container".to_string(), serde_json::Value::String(container_name.clone()));
-                            attributes.insert("source_type".to_string(), serde_json::Value::String("docker".to_string()));
-
-                            let entry = LogEntry {
-                                timestamp: pending_ts,
-                                level: pending_level,
-                                service: pending_service,
-                                message: pending_msg,
-                                attributes: Some(serde_json::Value::Object(attributes)),
-                            };
-
-                            if let Err(e) = tx.send(entry).await {
-                                error!("Failed to send log entry: {}", e);
-                                break;
-                            }
-                        }
-                    }
-                    
-                    let (parsed_level, final_message) = parse_log_level_and_clean(&cleaned_message);
-                    let level = parsed_level.unwrap_or_else(|| "INFO".to_string());
-                    
-                    if let Some((ref _ts, ref _level, ref _service, ref mut msg)) = pending_log {
-                        msg.push('\n');
-                        msg.push_str(&final_message);
-                    } else {
-                        pending_log = Some((ts, level, container_name.clone(), final_message));
-                    }
+                let received = docker_ts.unwrap_or_else(Utc::now);
+                let (pending, default_severity, attributes) = if is_stderr {
+                    (&mut stderr, context.stderr_severity, &stderr_attributes)
                 } else {
-                    if let Some((ref _ts, ref _level, ref _service, ref mut msg)) = pending_log {
-                        msg.push('\n');
-                        msg.push_str(log_text);
-                        
-                        if bracket_depth == 0 {
-                            if let Some((pending_ts, pending_level, pending_service, pending_msg)) = pending_log.take() {
-                                let mut attributes = serde_json::Map::new();
-                                attributes.insert("container".to_string(), serde_json::Value::String(container_name.clone()));
-                                attributes.insert("source_type".to_string(), serde_json::Value::String("docker".to_string()));
-
-                                let entry = LogEntry {
-                                    timestamp: pending_ts,
-                                    level: pending_level,
-                                    service: pending_service,
-                                    message: pending_msg,
-                                    attributes: Some(serde_json::Value::Object(attributes)),
-                                };
-
-                                if let Err(e) = tx.send(entry).await {
-                                    error!("Failed to send log entry: {}", e);
-                                    break;
-                                }
-                            }
-                        }
-                    } else {
-                        let (parsed_level, final_message) = parse_log_level_and_clean(&log_text);
-                        let level = parsed_level.unwrap_or_else(|| "INFO".to_string());
-                        
-                        let mut attributes = serde_json::Map::new();
-                        attributes.insert("container".to_string(), serde_json::Value::String(container_name.clone()));
-                        attributes.insert("source_type".to_string(), serde_json::Value::String("docker".to_string()));
-
-                        let entry = LogEntry {
-                            timestamp: chrono::Utc::now(),
-                            level,
-                            service: container_name.clone(),
-                            message: final_message,
-                            attributes: Some(serde_json::Value::Object(attributes)),
-                        };
-
-                        if let Err(e) = tx.send(entry).await {
-                            error!("Failed to send log entry: {}", e);
-                            break;
-                        }
+                    (&mut stdout, Severity::INFO, &stdout_attributes)
+                };
+
+                for (timestamp, severity, message) in pending.push(log_text, received, &context.severity, default_severity) {
+                    let entry = LogEntry {
+                        timestamp,
+                        observed_timestamp: Utc::now(),
+                        severity,
+                        service: metadata.service.clone(),
+                        message,
+                        body: None,
+                        attributes: Some(attributes.clone()),
+                        trace: None,
+                        scope: None,
+                        resource: Some(resource.clone()),
+                    };
+
+                    if let Err(e) = context.tx.send(entry).await {
+                        error!("Failed to send log entry: {}", e);
+                        return Ok(());
                     }
                 }
+
+                if docker_ts.is_some() {
+                    let resume_at = stdout.resume_point(received).min(stderr.resume_point(received));
+                    context.state.set(&container_id, format_checkpoint(resume_at));
+                    last_seen = Some(received);
+                }
             }
             Err(e) => {
                 error!("Error reading logs from container {}: {}", container_name, e);
@@ -259,6 +576,31 @@ async fn watch_container(
         }
     }
 
+    // Flush whatever was still waiting for a continuation line
+    for (pending, attributes) in [(stdout, &stdout_attributes), (stderr, &stderr_attributes)] {
+        if let Some((timestamp, severity, message)) = pending.entry {
+            let entry = LogEntry {
+                timestamp,
+                observed_timestamp: Utc::now(),
+                severity,
+                service: metadata.service.clone(),
+                message,
+                body: None,
+                attributes: Some(attributes.clone()),
+                trace: None,
+                scope: None,
+                resource: Some(resource.clone()),
+            };
+            if context.tx.send(entry).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if let Some(ts) = last_seen {
+        context.state.set(&container_id, format_checkpoint(ts));
+    }
+
+    info!("Log stream ended for container: {}", container_name);
     Ok(())
 }
 
@@ -279,32 +621,242 @@ impl LogProvider for DockerProvider {
             .context("Failed to connect to Docker daemon")?;
         
         info!("Connected to Docker daemon");
-        info!("Watching {} containers: {:?}", docker_config.containers.len(), docker_config.containers);
 
-        let mut handles = vec![];
-        
-        for container_name in &docker_config.containers {
-            let docker_clone = docker.clone();
-            let container_name_clone = container_name.clone();
-            let tx_clone = tx.clone();
-            
-            let handle = tokio::spawn(async move {
-                if let Err(e) = watch_container(docker_clone, container_name_clone.clone(), tx_clone).await {
-                    error!("Error watching container {}: {}", container_name_clone, e);
-                }
-            });
-            
-            handles.push(handle);
+        let selector = ContainerSelector::from_config(docker_config)?;
+        if selector.is_empty() {
+            warn!("No containers, labels or `all = true` configured for Docker provider");
+            return Ok(());
         }
 
-        for handle in handles {
-            let _ = handle.await;
-        }
+        let stderr_severity = match &docker_config.stderr_severity {
+            Some(value) => Severity::parse(value).with_context(|| {
+                format!("sources.docker.stderr_severity: unknown severity '{}'", value)
+            })?,
+            None => Severity::INFO,
+        };
+
+        let service_name = docker_config
+            .service_name
+            .as_deref()
+            .map(ServiceNameTemplate::parse)
+            .transpose()
+            .context("sources.docker.service_name")?
+            .map(Arc::new);
+
+        let mut attachments = Attachments {
+            context: WatchContext {
+                docker: docker.clone(),
+                severity: Arc::new(SeverityMapper::from_config(&self.config.severity)?),
+                stderr_severity,
+                labels: Arc::new(docker_config.labels.clone()),
+                service_name,
+                metadata: Arc::new(std::sync::Mutex::new(HashMap::new())),
+                state: self.state.clone(),
+                tx: tx.clone(),
+            },
+            tasks: JoinSet::new(),
+            watched: HashMap::new(),
+        };
+        attachments.tasks.spawn(self.state.clone().flush_every(Duration::from_secs(1)));
 
-        Ok(())
+        loop {
+            // Subscribe before listing so containers started in between are not missed
+            let mut filters = HashMap::new();
+            filters.insert("type".to_string(), vec!["container".to_string()]);
+            filters.insert("event".to_string(), vec!["start".to_string(), "destroy".to_string()]);
+
+            let mut events = docker.events(Some(EventsOptions::<String> {
+                filters,
+                ..Default::default()
+            }));
+
+            if let Err(e) = attach_running(&docker, &selector, &mut attachments).await {
+                error!("Failed to discover running containers: {}", e);
+            }
+            info!("Watching {} containers", attachments.watched.len());
+
+            loop {
+                let event = tokio::select! {
+                    event = events.next() => event,
+                    _ = tx.closed() => return Ok(()),
+                };
+                let Some(event) = event else { break };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        error!("Error reading Docker events: {}", e);
+                        break;
+                    }
+                };
+
+                let Some(actor) = event.actor else { continue };
+                let Some(id) = actor.id else { continue };
+                let attributes = actor.attributes.unwrap_or_default();
+                let name = attributes.get("name").cloned().unwrap_or_else(|| id.clone());
+
+                match event.action.as_deref() {
+                    Some("start") if selector.matches(&name, &attributes) => {
+                        info!("Container started: {}", name);
+                        // A restart may race the old stream's EOF; replace it
+                        attachments.detach(&id);
+                        attachments.attach(&id, &name, event.time);
+                    }
+                    Some("destroy") => {
+                        if attachments.watched.contains_key(&id) {
+                            info!("Container removed: {}", name);
+                        }
+                        attachments.remove(&id);
+                    }
+                    _ => {}
+                }
+            }
+
+            warn!("Docker event stream ended, resubscribing in 5s");
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {}
+                _ = tx.closed() => return Ok(()),
+            }
+        }
     }
     
     fn name(&self) -> &str {
         "docker"
     }
+
+    fn state(&self) -> Option<&StateStore> {
+        Some(&self.state)
+    }
+
+    async fn check(&self) -> Result<Vec<String>> {
+        let Some(docker_config) = &self.config.sources.docker else {
+            return Ok(Vec::new());
+        };
+        let selector = ContainerSelector::from_config(docker_config)?;
+        if let Some(template) = &docker_config.service_name {
+            ServiceNameTemplate::parse(template).context("sources.docker.service_name")?;
+        }
+        if let Some(value) = &docker_config.stderr_severity {
+            Severity::parse(value)
+                .with_context(|| format!("sources.docker.stderr_severity: unknown severity '{}'", value))?;
+        }
+        if selector.is_empty() {
+            return Ok(vec!["no containers, labels or `all = true` configured".to_string()]);
+        }
+
+        let docker = Docker::connect_with_local_defaults().context("Failed to connect to Docker daemon")?;
+        let names: Vec<String> = matching_containers(&docker, &selector)
+            .await?
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        Ok(vec![format!("{} running containers match: {}", names.len(), names.join(", "))])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(containers: &[&str], exclude: &[&str], labels: &[&str], all: bool) -> DockerSource {
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        DockerSource {
+            enabled: true,
+            containers: strings(containers),
+            all,
+            include_labels: strings(labels),
+            exclude_names: strings(exclude),
+            labels: Vec::new(),
+            stderr_severity: None,
+            service_name: None,
+        }
+    }
+
+    #[test]
+    fn test_selector_names_and_excludes() {
+        let selector = ContainerSelector::from_config(&source(&["web-*", "redis"], &["web-test*"], &[], false)).unwrap();
+        let labels = HashMap::new();
+        assert!(selector.matches("web-1", &labels));
+        assert!(selector.matches("redis", &labels));
+        assert!(!selector.matches("web-test-1", &labels));
+        assert!(!selector.matches("postgres", &labels));
+    }
+
+    #[test]
+    fn test_selector_labels_and_all() {
+        let selector = ContainerSelector::from_config(&source(&[], &["ilog-agent"], &["ilog.enable=true", "com.docker.compose.project"], false)).unwrap();
+        let mut labels = HashMap::new();
+        assert!(!selector.matches("api", &labels));
+        labels.insert("ilog.enable".to_string(), "false".to_string());
+        assert!(!selector.matches("api", &labels));
+        labels.insert("com.docker.compose.project".to_string(), "shop".to_string());
+        assert!(selector.matches("api", &labels));
+
+        let selector = ContainerSelector::from_config(&source(&[], &["ilog-agent"], &[], true)).unwrap();
+        assert!(selector.matches("anything", &HashMap::new()));
+        assert!(!selector.matches("ilog-agent", &HashMap::new()));
+    }
+
+    #[test]
+    fn test_container_service() {
+        let metadata = ContainerMetadata {
+            id: "abc".to_string(),
+            name: "shop-api-1".to_string(),
+            service: "shop-api-1".to_string(),
+            image: Some("ghcr.io/acme/api".to_string()),
+            image_tag: Some("v2".to_string()),
+            compose_project: Some("shop".to_string()),
+            compose_service: Some("api".to_string()),
+            labels: Vec::new(),
+        };
+        let labels = HashMap::from([("com.acme.service".to_string(), "orders".to_string())]);
+        let render = |template: &str| container_service(&ServiceNameTemplate::parse(template).unwrap(), &metadata, &labels);
+
+        assert_eq!(render("{compose.project}-{compose.service}").as_deref(), Some("shop-api"));
+        assert_eq!(render("{label:com.acme.service}").as_deref(), Some("orders"));
+        assert_eq!(render("{image}").as_deref(), Some("ghcr.io/acme/api"));
+        assert_eq!(render("{label:missing}"), None);
+    }
+
+    #[test]
+    fn test_split_image() {
+        assert_eq!(split_image("nginx"), ("nginx".to_string(), None));
+        assert_eq!(split_image("nginx:1.25"), ("nginx".to_string(), Some("1.25".to_string())));
+        assert_eq!(
+            split_image("registry:5000/team/app:v2@sha256:abc"),
+            ("registry:5000/team/app".to_string(), Some("v2".to_string()))
+        );
+        assert_eq!(split_image("registry:5000/app"), ("registry:5000/app".to_string(), None));
+    }
+
+    #[test]
+    fn test_pending_log_multiline_json() {
+        let mapper = SeverityMapper::from_config(&Default::default()).unwrap();
+        let mut pending = PendingLog::default();
+
+        let received = Utc::now();
+
+        assert!(pending.push("2025-01-01T00:00:00Z ERROR payload {", received, &mapper, Severity::INFO).is_empty());
+        assert_eq!(pending.resume_point(received), received - chrono::Duration::nanoseconds(1));
+        assert!(pending.push("\"id\": 1", received, &mapper, Severity::INFO).is_empty());
+        let complete = pending.push("}", received, &mapper, Severity::INFO);
+        assert_eq!(complete.len(), 1);
+        assert_eq!(complete[0].1, Severity::ERROR);
+        assert_eq!(complete[0].2, "payload {\n\"id\": 1\n}");
+        assert_eq!(pending.resume_point(received), received);
+
+        let complete = pending.push("no timestamp here", received, &mapper, Severity::WARN);
+        assert_eq!(complete[0].0, received);
+        assert_eq!(complete[0].1, Severity::WARN);
+    }
+
+    #[test]
+    fn test_split_docker_timestamp() {
+        let (ts, rest) = split_docker_timestamp("2025-03-01T10:00:00.123456789Z GET /health 200");
+        assert_eq!(format_checkpoint(ts.unwrap()), "2025-03-01T10:00:00.123456789Z");
+        assert_eq!(rest, "GET /health 200");
+
+        let (ts, rest) = split_docker_timestamp("plain line");
+        assert!(ts.is_none());
+        assert_eq!(rest, "plain line");
+    }
 }
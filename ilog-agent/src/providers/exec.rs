@@ -0,0 +1,332 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+use tokio::task::JoinSet;
+use tracing::{info, error, warn};
+
+use crate::config::{AgentConfig, ExecCommand, RestartPolicy};
+use crate::severity::{Severity, SeverityMapper};
+use crate::tcp_sender::LogEntry;
+use super::LogProvider;
+
+pub struct ExecProvider {
+    config: Arc<AgentConfig>,
+}
+
+impl ExecProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        Self { config }
+    }
+}
+
+fn should_restart(policy: RestartPolicy, status: Option<ExitStatus>) -> bool {
+    match policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::OnFailure => !status.map(|s| s.success()).unwrap_or(false),
+        RestartPolicy::Always => true,
+    }
+}
+
+/// Doubles the delay up to `max`, starting over once a run lasted longer than `max`
+fn next_backoff(current: Duration, initial: Duration, max: Duration, ran_for: Duration) -> Duration {
+    if ran_for >= max {
+        initial
+    } else {
+        (current * 2).min(max)
+    }
+}
+
+/// Human readable description and exit code of a finished process
+fn describe_exit(status: &ExitStatus) -> (String, Option<i32>) {
+    if let Some(code) = status.code() {
+        return (format!("Process exited with code {}", code), Some(code));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return (format!("Process killed by signal {}", signal), None);
+        }
+    }
+    ("Process exited".to_string(), None)
+}
+
+#[derive(Clone)]
+struct CommandContext {
+    command: Arc<ExecCommand>,
+    severity: Arc<SeverityMapper>,
+    stderr_severity: Severity,
+    tx: mpsc::Sender<LogEntry>,
+}
+
+impl CommandContext {
+    fn entry(&self, severity: Severity, message: String, attributes: serde_json::Map<String, serde_json::Value>) -> LogEntry {
+        let mut attributes = attributes;
+        attributes.insert("source_type".to_string(), "exec".into());
+        attributes.insert("exec.command".to_string(), self.command.command.as_str().into());
+
+        LogEntry {
+            timestamp: chrono::Utc::now(),
+            observed_timestamp: chrono::Utc::now(),
+            severity,
+            service: self.command.name.clone(),
+            message,
+            body: None,
+            attributes: Some(serde_json::Value::Object(attributes)),
+            trace: None,
+            scope: None,
+            resource: None,
+        }
+    }
+
+    /// Forward one output stream line by line
+    async fn read_stream<R: AsyncRead + Unpin>(self, reader: R, stream: &'static str, pid: Option<u32>) {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        let default_severity = if stream == "stderr" { self.stderr_severity } else { Severity::INFO };
+
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line).await {
+                Ok(0) => return,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to read {} of {}: {}", stream, self.command.name, e);
+                    return;
+                }
+            }
+
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\n', '\r']);
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            let mut attributes = serde_json::Map::new();
+            attributes.insert("log.iostream".to_string(), stream.into());
+            if let Some(pid) = pid {
+                attributes.insert("exec.pid".to_string(), pid.into());
+            }
+
+            let severity = self.severity.detect(text).unwrap_or(default_severity);
+            if self.tx.send(self.entry(severity, text.to_string(), attributes)).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Run the command once, returning its exit status (None if it could not be started)
+    async fn run_once(&self) -> Result<Option<ExitStatus>> {
+        let mut command = Command::new(&self.command.command);
+        command
+            .args(&self.command.args)
+            .envs(&self.command.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(dir) = &self.command.working_dir {
+            command.current_dir(dir);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                error!("Failed to start {}: {}", self.command.name, e);
+                let mut attributes = serde_json::Map::new();
+                attributes.insert("exec.event".to_string(), "spawn_failed".into());
+                let message = format!("Failed to start {}: {}", self.command.command, e);
+                let _ = self.tx.send(self.entry(Severity::ERROR, message, attributes)).await;
+                return Ok(None);
+            }
+        };
+
+        let pid = child.id();
+        info!("Started {} (pid {:?})", self.command.name, pid);
+
+        let stdout = child.stdout.take().context("stdout was not captured")?;
+        let stderr = child.stderr.take().context("stderr was not captured")?;
+        let stdout_reader = tokio::spawn(self.clone().read_stream(stdout, "stdout", pid));
+        let stderr_reader = tokio::spawn(self.clone().read_stream(stderr, "stderr", pid));
+
+        let status = tokio::select! {
+            status = child.wait() => status.context("Failed to wait for process")?,
+            // The provider was stopped; kill_on_drop would only act once the child is dropped
+            _ = self.tx.closed() => {
+                info!("Stopping {} (pid {:?})", self.command.name, pid);
+                let _ = child.kill().await;
+                return Ok(None);
+            }
+        };
+        // Drain whatever the process wrote before exiting
+        let _ = stdout_reader.await;
+        let _ = stderr_reader.await;
+
+        let (message, code) = describe_exit(&status);
+        let mut attributes = serde_json::Map::new();
+        attributes.insert("exec.event".to_string(), "exit".into());
+        if let Some(code) = code {
+            attributes.insert("exec.exit_code".to_string(), code.into());
+        }
+        if let Some(pid) = pid {
+            attributes.insert("exec.pid".to_string(), pid.into());
+        }
+        let severity = if status.success() { Severity::INFO } else { Severity::ERROR };
+        let _ = self.tx.send(self.entry(severity, message, attributes)).await;
+
+        Ok(Some(status))
+    }
+
+    async fn supervise(self) -> Result<()> {
+        let initial = Duration::from_secs(self.command.backoff_initial_secs);
+        let max = Duration::from_secs(self.command.backoff_max_secs).max(initial);
+        let mut delay = initial;
+
+        loop {
+            let started = Instant::now();
+            let status = self.run_once().await?;
+
+            if self.tx.is_closed() || !should_restart(self.command.restart, status) {
+                return Ok(());
+            }
+
+            delay = next_backoff(delay, initial, max, started.elapsed());
+            info!("Restarting {} in {:?}", self.command.name, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LogProvider for ExecProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let exec_config = match &self.config.sources.exec {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("Exec provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        let severity = Arc::new(SeverityMapper::from_config(&self.config.severity)?);
+        // Dropped with this future, so stopping the provider stops the commands
+        let mut commands = JoinSet::new();
+
+        for command in exec_config.commands {
+            let stderr_severity = match &command.stderr_severity {
+                Some(value) => Severity::parse(value).with_context(|| {
+                    format!("sources.exec.commands.{}.stderr_severity: unknown severity '{}'", command.name, value)
+                })?,
+                None => Severity::INFO,
+            };
+
+            let context = CommandContext {
+                command: Arc::new(command),
+                severity: severity.clone(),
+                stderr_severity,
+                tx: tx.clone(),
+            };
+            let name = context.command.name.clone();
+            commands.spawn(async move {
+                if let Err(e) = context.supervise().await {
+                    error!("Exec command {} failed: {:#}", name, e);
+                }
+            });
+        }
+
+        while commands.join_next().await.is_some() {}
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "exec"
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn command(script: &str, restart: RestartPolicy) -> ExecCommand {
+        ExecCommand {
+            name: "job".to_string(),
+            command: "sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            env: HashMap::from([("GREETING".to_string(), "hello".to_string())]),
+            working_dir: Some(std::env::temp_dir()),
+            restart,
+            backoff_initial_secs: 0,
+            backoff_max_secs: 0,
+            stderr_severity: Some("warn".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let initial = Duration::from_secs(1);
+        let max = Duration::from_secs(8);
+        let short = Duration::from_millis(10);
+        assert_eq!(next_backoff(initial, initial, max, short), Duration::from_secs(2));
+        assert_eq!(next_backoff(Duration::from_secs(6), initial, max, short), max);
+        assert_eq!(next_backoff(max, initial, max, Duration::from_secs(30)), initial);
+    }
+
+    #[tokio::test]
+    async fn test_captures_streams_and_exit() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let context = CommandContext {
+            command: Arc::new(command("echo $GREETING; echo oops >&2; exit 3", RestartPolicy::Never)),
+            severity: Arc::new(SeverityMapper::from_config(&Default::default()).unwrap()),
+            stderr_severity: Severity::WARN,
+            tx,
+        };
+        context.supervise().await.unwrap();
+
+        let mut entries = vec![];
+        while let Ok(entry) = rx.try_recv() {
+            entries.push(entry);
+        }
+        assert_eq!(entries.len(), 3);
+
+        let stdout = entries.iter().find(|e| e.message == "hello").unwrap();
+        assert_eq!(stdout.attributes.as_ref().unwrap()["log.iostream"], "stdout");
+        assert_eq!(stdout.severity, Severity::INFO);
+
+        let stderr = entries.iter().find(|e| e.message == "oops").unwrap();
+        assert_eq!(stderr.attributes.as_ref().unwrap()["log.iostream"], "stderr");
+        assert_eq!(stderr.severity, Severity::WARN);
+
+        let exit = entries.last().unwrap();
+        assert_eq!(exit.message, "Process exited with code 3");
+        assert_eq!(exit.severity, Severity::ERROR);
+        assert_eq!(exit.attributes.as_ref().unwrap()["exec.exit_code"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_restart_on_failure() {
+        let marker = std::env::temp_dir().join(format!("ilog-exec-test-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        // Fails the first time, succeeds the second
+        let script = format!("if [ -e {0} ]; then exit 0; else touch {0}; exit 1; fi", marker.display());
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let context = CommandContext {
+            command: Arc::new(command(&script, RestartPolicy::OnFailure)),
+            severity: Arc::new(SeverityMapper::from_config(&Default::default()).unwrap()),
+            stderr_severity: Severity::INFO,
+            tx,
+        };
+        context.supervise().await.unwrap();
+
+        assert_eq!(rx.try_recv().unwrap().attributes.unwrap()["exec.exit_code"], 1);
+        assert_eq!(rx.try_recv().unwrap().attributes.unwrap()["exec.exit_code"], 0);
+        assert!(rx.try_recv().is_err());
+        std::fs::remove_file(&marker).unwrap();
+    }
+}
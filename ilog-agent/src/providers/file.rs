@@ -1,78 +1,135 @@
 use tokio::sync::mpsc;
 use anyhow::{Result, Context};
+use std::borrow::Cow;
 use std::sync::Arc;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
+use tokio::task::JoinSet;
 use tracing::{info, error, warn};
 use notify::{Watcher, RecursiveMode, EventKind};
 
 use crate::config::AgentConfig;
+use crate::health::{self, Health};
+use crate::service_name::ServiceNameTemplate;
+use crate::severity::{Severity, SeverityMapper};
+use crate::state::{file_identity, saved_offset, StateStore};
 use crate::tcp_sender::LogEntry;
 use super::LogProvider;
 
 pub struct FileProvider {
     config: Arc<AgentConfig>,
+    health: Arc<Health>,
+    state: Arc<StateStore>,
+}
+
+/// Service name for a file: the rendered template, or the file name
+fn service_for(path: &Path, template: Option<&ServiceNameTemplate>, pattern: Option<&regex::Regex>) -> String {
+    let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("unknown");
+    let Some(template) = template else {
+        return file_name.to_string();
+    };
+
+    let path_str = path.to_string_lossy();
+    let captures = pattern.and_then(|pattern| pattern.captures(&path_str));
+    let rendered = template.render(|name| match name {
+        "file" => Some(Cow::Borrowed(file_name)),
+        "stem" => path.file_stem().and_then(|s| s.to_str()).map(Cow::Borrowed),
+        "dir" => path.parent()?.file_name()?.to_str().map(Cow::Borrowed),
+        _ => {
+            let captures = captures.as_ref()?;
+            let group = match name.parse::<usize>() {
+                Ok(index) => captures.get(index),
+                Err(_) => captures.name(name),
+            };
+            group.map(|m| Cow::Owned(m.as_str().to_string()))
+        }
+    });
+
+    rendered.unwrap_or_else(|| {
+        warn!("Service name template does not resolve for {}, using the file name", path.display());
+        file_name.to_string()
+    })
+}
+
+/// Files a `paths` entry refers to: a directory means every *.log file below it
+fn expand(path_pattern: &str) -> Result<Vec<PathBuf>> {
+    let pattern = if Path::new(path_pattern).is_dir() {
+        info!("Discovering *.log files in directory: {}", path_pattern);
+        format!("{}/**/*.log", path_pattern.trim_end_matches('/'))
+    } else {
+        path_pattern.to_string()
+    };
+
+    let paths = glob::glob(&pattern).with_context(|| format!("Invalid glob pattern {}", path_pattern))?;
+    Ok(paths.flatten().filter(|path| path.is_file()).collect())
 }
 
 impl FileProvider {
-    pub fn new(config: Arc<AgentConfig>) -> Self {
-        Self { config }
+    pub fn new(config: Arc<AgentConfig>, health: Arc<Health>) -> Self {
+        let state = StateStore::open(&config.agent.state_dir, "file");
+        Self { config, health, state }
     }
 
-    async fn tail_file(path: PathBuf, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    async fn tail_file(
+        path: PathBuf,
+        service: String,
+        severity: Arc<SeverityMapper>,
+        state: Arc<StateStore>,
+        tx: mpsc::Sender<LogEntry>,
+    ) -> Result<()> {
         info!("Starting to tail file: {}", path.display());
         
+        let key = path.to_string_lossy().into_owned();
+        let (inode, len) = file_identity(&path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
         let file = File::open(&path).await
             .context(format!("Failed to open file: {}", path.display()))?;
         
         let mut reader = BufReader::new(file);
         
-        // Seek to end of file (like tail -f)
-        reader.seek(std::io::SeekFrom::End(0)).await?;
-        info!("Positioned at end of file: {}", path.display());
+        // Resume where the last run stopped; a file without a saved offset
+        // (or a rotated or truncated one) is read from the end, like tail -f
+        let mut offset = saved_offset(&state, &key, inode).filter(|&offset| offset <= len).unwrap_or(len);
+        reader.seek(std::io::SeekFrom::Start(offset)).await?;
+        info!("Positioned at offset {} of {}", offset, path.display());
         
+        let (notify_tx, notify_rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(notify_tx)
+            .context("Failed to create file watcher")?;
+        watcher.watch(&path, RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch file: {}", path.display()))?;
+        info!("File watcher started for: {}", path.display());
+
+        // notify delivers events on a std channel; forward them from a thread.
+        // The thread owns the watcher and ends on the first event after this tailer is gone.
         let (watch_tx, mut watch_rx) = tokio::sync::mpsc::channel(100);
-        let watch_path = path.clone();
-        
         std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                let (notify_tx, notify_rx) = std::sync::mpsc::channel();
-                
-                let mut watcher = notify::recommended_watcher(notify_tx)
-                    .expect("Failed to create file watcher");
-                
-                watcher.watch(&watch_path, RecursiveMode::NonRecursive)
-                    .expect("Failed to watch file");
-                
-                info!("File watcher started for: {}", watch_path.display());
-                
-                loop {
-                    match notify_rx.recv() {
-                        Ok(Ok(event)) => {
-                            if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
-                                let _ = watch_tx.send(()).await;
-                            }
-                        }
-                        Ok(Err(e)) => {
-                            error!("Watch error: {:?}", e);
-                        }
-                        Err(e) => {
-                            error!("Channel error: {:?}", e);
+            let _watcher = watcher;
+            loop {
+                match notify_rx.recv() {
+                    Ok(Ok(event)) => {
+                        if matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+                            && watch_tx.blocking_send(()).is_err()
+                        {
                             break;
                         }
                     }
+                    Ok(Err(e)) => {
+                        error!("Watch error: {:?}", e);
+                    }
+                    Err(e) => {
+                        error!("Channel error: {:?}", e);
+                        break;
+                    }
                 }
-            });
+            }
         });
         
         loop {
-            // Wait for file change notification
-            let _ = watch_rx.recv().await;
-            info!("File change detected: {}", path.display());
-            
-            // Read all available complete lines
+            // Read all available complete lines, including those written
+            // while the agent was stopped
             let mut line = String::new();
             loop {
                 line.clear();
@@ -85,10 +142,10 @@ impl FileProvider {
                 // Only process if we got a complete line (ends with newline)
                 if !line.ends_with('\n') {
                     // Incomplete line, seek back and wait for next event
-                    let current_pos = reader.stream_position().await?;
-                    reader.seek(std::io::SeekFrom::Start(current_pos.saturating_sub(bytes_read as u64))).await?;
+                    reader.seek(std::io::SeekFrom::Start(offset)).await?;
                     break;
                 }
+                offset += bytes_read as u64;
                 
                 let log_text = line.trim();
                 if log_text.is_empty() {
@@ -99,26 +156,37 @@ impl FileProvider {
                 
                 let entry = LogEntry {
                     timestamp: chrono::Utc::now(),
-                    level: "INFO".to_string(),
-                    service: path.file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("unknown")
-                        .to_string(),
+                    observed_timestamp: chrono::Utc::now(),
+                    severity: severity.detect(log_text).unwrap_or(Severity::INFO),
+                    service: service.clone(),
                     message: log_text.to_string(),
+                    body: None,
                     attributes: Some(serde_json::json!({
                         "source_type": "file",
                         "file_path": path.to_string_lossy(),
                     })),
+                    trace: None,
+                    scope: None,
+                    resource: None,
                 };
                 
-                info!("Sending log entry to channel from file: {}", path.display());
-                if let Err(e) = tx.send(entry).await {
-                    error!("Failed to send log entry: {}", e);
+                // Fails once the provider is stopped; the offset only covers what was forwarded
+                if tx.send(entry).await.is_err() {
                     return Ok(());
-                } else {
-                    info!("Log entry sent successfully");
                 }
+                state.set(&key, format!("{}:{}", inode, offset));
             }
+
+            // Wait for file change notification, or for the provider to stop
+            tokio::select! {
+                event = watch_rx.recv() => {
+                    if event.is_none() {
+                        anyhow::bail!("File watcher stopped for {}", path.display());
+                    }
+                }
+                _ = tx.closed() => return Ok(()),
+            }
+            info!("File change detected: {}", path.display());
         }
     }
 }
@@ -135,46 +203,32 @@ impl LogProvider for FileProvider {
         };
         
         info!("Starting file provider with {} paths", file_config.paths.len());
+
+        let severity = Arc::new(SeverityMapper::from_config(&self.config.severity)?);
+        let template = file_config
+            .service_name
+            .as_deref()
+            .map(ServiceNameTemplate::parse)
+            .transpose()
+            .context("sources.file.service_name")?;
+        let path_regex = file_config
+            .path_pattern
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .context("sources.file.path_pattern: invalid regular expression")?;
         
-        let mut handles = vec![];
+        // Dropped with this future, so stopping the provider stops the tailers
+        // and the flusher
+        let mut tailers = JoinSet::new();
+        let mut flusher = JoinSet::new();
+        flusher.spawn(self.state.clone().flush_every(Duration::from_secs(1)));
         let mut discovered_files = vec![];
         
         for path_pattern in &file_config.paths {
-            let path = std::path::Path::new(path_pattern.as_str());
-            if path.is_dir() {
-                // Recursively find all .log files in directory
-                info!("Discovering *.log files in directory: {}", path.display());
-                let pattern = format!("{}/**/*.log", path_pattern.trim_end_matches('/'));
-                match glob::glob(&pattern) {
-                    Ok(paths) => {
-                        for path_result in paths {
-                            if let Ok(file_path) = path_result {
-                                if file_path.is_file() {
-                                    discovered_files.push(file_path);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Failed to glob directory {}: {}", path.display(), e);
-                    }
-                }
-            } else {
-                // Expand glob patterns for files
-                match glob::glob(path_pattern) {
-                    Ok(paths) => {
-                        for path_result in paths {
-                            if let Ok(file_path) = path_result {
-                                if file_path.is_file() {
-                                    discovered_files.push(file_path);
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        error!("Invalid glob pattern {}: {}", path_pattern, e);
-                    }
-                }
+            match expand(path_pattern) {
+                Ok(files) => discovered_files.extend(files),
+                Err(e) => error!("{:#}", e),
             }
         }
         
@@ -182,20 +236,23 @@ impl LogProvider for FileProvider {
         
         for path in discovered_files {
             let tx_clone = tx.clone();
+            let severity = severity.clone();
+            let service = service_for(&path, template.as_ref(), path_regex.as_ref());
+            info!("Service name for {}: {}", path.display(), service);
             
-            let handle = tokio::spawn(async move {
-                if let Err(e) = Self::tail_file(path.clone(), tx_clone).await {
-                    error!("Error tailing file {}: {}", path.display(), e);
-                }
+            let health = self.health.clone();
+            let state = self.state.clone();
+            tailers.spawn(async move {
+                let name = format!("file:{}", path.display());
+                health::supervise(&health, &name, &tx_clone, || {
+                    Self::tail_file(path.clone(), service.clone(), severity.clone(), state.clone(), tx_clone.clone())
+                })
+                .await;
             });
-            
-            handles.push(handle);
         }
         
         // Wait for all file watchers
-        for handle in handles {
-            let _ = handle.await;
-        }
+        while tailers.join_next().await.is_some() {}
         
         Ok(())
     }
@@ -203,4 +260,67 @@ impl LogProvider for FileProvider {
     fn name(&self) -> &str {
         "file"
     }
+
+    fn state(&self) -> Option<&StateStore> {
+        Some(&self.state)
+    }
+
+    async fn check(&self) -> Result<Vec<String>> {
+        let Some(file_config) = &self.config.sources.file else {
+            return Ok(Vec::new());
+        };
+        if let Some(template) = &file_config.service_name {
+            ServiceNameTemplate::parse(template).context("sources.file.service_name")?;
+        }
+        if let Some(pattern) = &file_config.path_pattern {
+            regex::Regex::new(pattern).context("sources.file.path_pattern: invalid regular expression")?;
+        }
+
+        let mut notes = Vec::new();
+        for path_pattern in &file_config.paths {
+            let files = expand(path_pattern)?;
+            match files.first() {
+                Some(first) => notes.push(format!("{}: {} files (e.g. {})", path_pattern, files.len(), first.display())),
+                None => notes.push(format!("{}: no files match yet", path_pattern)),
+            }
+        }
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_for() {
+        let path = Path::new("/var/www/shop/storage/logs/laravel.log");
+        assert_eq!(service_for(path, None, None), "laravel.log");
+
+        let pattern = regex::Regex::new("^/var/www/(?P<app>[^/]+)/").unwrap();
+        let render = |template: &str| {
+            service_for(path, Some(&ServiceNameTemplate::parse(template).unwrap()), Some(&pattern))
+        };
+        assert_eq!(render("billing"), "billing");
+        assert_eq!(render("{app}-{stem}"), "shop-laravel");
+        assert_eq!(render("{1}/{dir}"), "shop/logs");
+        // A capture that doesn't exist falls back to the file name
+        assert_eq!(render("{2}"), "laravel.log");
+    }
+
+    #[test]
+    fn test_expand() {
+        let dir = std::env::temp_dir().join(format!("ilog-file-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a.log"), "").unwrap();
+        std::fs::write(dir.join("nested/b.log"), "").unwrap();
+        std::fs::write(dir.join("c.txt"), "").unwrap();
+
+        let dir_str = dir.to_str().unwrap();
+        assert_eq!(expand(dir_str).unwrap().len(), 2);
+        assert_eq!(expand(&format!("{}/*", dir_str)).unwrap().len(), 2);
+        assert!(expand(&format!("{}/[", dir_str)).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,225 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::config::AgentConfig;
+use crate::record::RecordParser;
+use crate::severity::SeverityMapper;
+use crate::tcp_sender::LogEntry;
+use super::LogProvider;
+
+pub struct HttpProvider {
+    config: Arc<AgentConfig>,
+}
+
+impl HttpProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        Self { config }
+    }
+}
+
+#[derive(Clone)]
+struct PushState {
+    parser: Arc<RecordParser>,
+    token: Option<Arc<str>>,
+    tx: mpsc::Sender<LogEntry>,
+}
+
+/// Compare without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Shared secret from `Authorization: Bearer <token>` or `X-Ilog-Token`
+fn authorized(headers: &HeaderMap, token: &str) -> bool {
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-ilog-token").and_then(|value| value.to_str().ok()));
+
+    provided.is_some_and(|provided| constant_time_eq(provided.trim().as_bytes(), token.as_bytes()))
+}
+
+/// A JSON array or object for `application/json`, otherwise NDJSON / plain lines
+fn parse_body(parser: &RecordParser, headers: &HeaderMap, body: &[u8]) -> Result<Vec<LogEntry>, String> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if is_json {
+        return match serde_json::from_slice(body).map_err(|e| format!("invalid JSON: {}", e))? {
+            Value::Array(records) => Ok(records.into_iter().map(|record| parser.parse_value(record)).collect()),
+            record => Ok(vec![parser.parse_value(record)]),
+        };
+    }
+
+    let body = std::str::from_utf8(body).map_err(|_| "body is not valid UTF-8".to_string())?;
+    Ok(body.lines().filter_map(|line| parser.parse_line(line)).collect())
+}
+
+async fn push(State(state): State<PushState>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Some(token) = &state.token {
+        if !authorized(&headers, token) {
+            return (StatusCode::UNAUTHORIZED, "invalid or missing token").into_response();
+        }
+    }
+
+    let entries = match parse_body(&state.parser, &headers, &body) {
+        Ok(entries) => entries,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let accepted = entries.len();
+    if accepted == 0 {
+        return (StatusCode::ACCEPTED, axum::Json(serde_json::json!({ "accepted": 0 }))).into_response();
+    }
+    if accepted > state.tx.max_capacity() {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "too many records in one request").into_response();
+    }
+
+    // Reserve room for the whole batch so a request is either queued completely or rejected
+    let permits = match state.tx.try_reserve_many(accepted) {
+        Ok(permits) => permits,
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "1")], "agent queue is full").into_response();
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => return StatusCode::SERVICE_UNAVAILABLE.into_response(),
+    };
+    for (permit, entry) in permits.zip(entries) {
+        permit.send(entry);
+    }
+
+    (StatusCode::ACCEPTED, axum::Json(serde_json::json!({ "accepted": accepted }))).into_response()
+}
+
+#[async_trait::async_trait]
+impl LogProvider for HttpProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let http_config = match &self.config.sources.http {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("HTTP provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        let state = PushState {
+            parser: Arc::new(RecordParser::new(
+                http_config.service.clone(),
+                "http",
+                SeverityMapper::from_config(&self.config.severity)?,
+            )),
+            token: http_config.token.as_deref().map(Arc::from),
+            tx: tx.clone(),
+        };
+
+        let listener = tokio::net::TcpListener::bind(&http_config.listen)
+            .await
+            .with_context(|| format!("Failed to bind HTTP listener {}", http_config.listen))?;
+        info!(
+            "Accepting pushed logs on http://{}{} (auth: {})",
+            http_config.listen,
+            http_config.path,
+            if state.token.is_some() { "token" } else { "none" }
+        );
+
+        let app = Router::new()
+            .route(&http_config.path, post(push))
+            .layer(DefaultBodyLimit::max(http_config.max_body_bytes))
+            .with_state(state);
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move { tx.closed().await })
+            .await
+            .context("HTTP push server failed")
+    }
+
+    fn name(&self) -> &str {
+        "http"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn state(capacity: usize, token: Option<&str>) -> (PushState, mpsc::Receiver<LogEntry>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let parser = RecordParser::new("pushed", "http", SeverityMapper::from_config(&Default::default()).unwrap());
+        let state = PushState {
+            parser: Arc::new(parser),
+            token: token.map(Arc::from),
+            tx,
+        };
+        (state, rx)
+    }
+
+    fn json_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_json_array_and_ndjson() {
+        let (state, mut rx) = state(10, None);
+
+        let body = Bytes::from(r#"[{"message":"a","level":"error"},{"message":"b","service":"api"}]"#);
+        let response = push(State(state.clone()), json_headers(), body).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.service, "pushed");
+        assert_eq!(first.attributes.unwrap()["source_type"], "http");
+        assert_eq!(rx.recv().await.unwrap().service, "api");
+
+        let body = Bytes::from("{\"msg\":\"c\"}\nplain line\n\n");
+        let response = push(State(state.clone()), HeaderMap::new(), body).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(rx.recv().await.unwrap().message, "c");
+        assert_eq!(rx.recv().await.unwrap().message, "plain line");
+
+        let response = push(State(state), json_headers(), Bytes::from("[{")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_token_auth() {
+        let (state, _rx) = state(10, Some("s3cret"));
+        let body = || Bytes::from("line");
+
+        let response = push(State(state.clone()), HeaderMap::new(), body()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer wrong"));
+        assert_eq!(push(State(state.clone()), headers, body()).await.status(), StatusCode::UNAUTHORIZED);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ilog-token", HeaderValue::from_static("s3cret"));
+        assert_eq!(push(State(state), headers, body()).await.status(), StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_queue_full() {
+        let (state, _rx) = state(2, None);
+
+        let response = push(State(state.clone()), HeaderMap::new(), Bytes::from("one\ntwo")).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = push(State(state.clone()), HeaderMap::new(), Bytes::from("three")).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        let response = push(State(state), HeaderMap::new(), Bytes::from("1\n2\n3")).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,587 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, BufReader};
+use tokio::task::{AbortHandle, JoinSet};
+use tracing::{info, error, warn, debug};
+
+use crate::config::{AgentConfig, KubernetesSource};
+use crate::severity::{Severity, SeverityMapper};
+use crate::state::{file_identity, saved_offset, StateStore};
+use crate::tcp_sender::LogEntry;
+use super::LogProvider;
+
+/// How often the pod log directory is rescanned for new containers
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(5);
+/// How often the kubelet pod list is refreshed
+const KUBELET_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+/// Partial (`P`) lines are flushed once they grow past this size
+const MAX_PARTIAL_SIZE: usize = 1024 * 1024;
+
+pub struct KubernetesProvider {
+    config: Arc<AgentConfig>,
+    state: Arc<StateStore>,
+}
+
+impl KubernetesProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        let state = StateStore::open(&config.agent.state_dir, "kubernetes");
+        Self { config, state }
+    }
+}
+
+/// Identity of a container log file, taken from
+/// `<log_dir>/<namespace>_<pod>_<uid>/<container>/<restart>.log`
+#[derive(Debug, Clone, PartialEq)]
+struct PodLogPath {
+    namespace: String,
+    pod: String,
+    uid: String,
+    container: String,
+    restart_count: u32,
+}
+
+fn parse_pod_path(path: &Path) -> Option<PodLogPath> {
+    let restart_count = path.file_stem()?.to_str()?.parse().ok()?;
+    let container = path.parent()?.file_name()?.to_str()?;
+    let pod_dir = path.parent()?.parent()?.file_name()?.to_str()?;
+
+    // Namespaces and pod names cannot contain '_', so the split is unambiguous
+    let mut parts = pod_dir.splitn(3, '_');
+    let namespace = parts.next().filter(|s| !s.is_empty())?;
+    let pod = parts.next().filter(|s| !s.is_empty())?;
+    let uid = parts.next().filter(|s| !s.is_empty())?;
+
+    Some(PodLogPath {
+        namespace: namespace.to_string(),
+        pod: pod.to_string(),
+        uid: uid.to_string(),
+        container: container.to_string(),
+        restart_count,
+    })
+}
+
+/// One line of the CRI logging format: `<RFC3339Nano> <stream> <P|F> <content>`
+#[derive(Debug, PartialEq)]
+struct CriLine<'a> {
+    timestamp: DateTime<Utc>,
+    stream: &'a str,
+    partial: bool,
+    content: &'a str,
+}
+
+fn parse_cri_line(line: &str) -> Option<CriLine<'_>> {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let mut parts = line.splitn(4, ' ');
+    let timestamp = DateTime::parse_from_rfc3339(parts.next()?).ok()?.with_timezone(&Utc);
+    let stream = parts.next().filter(|s| *s == "stdout" || *s == "stderr")?;
+    let partial = match parts.next()? {
+        "P" => true,
+        "F" => false,
+        _ => return None,
+    };
+
+    Some(CriLine {
+        timestamp,
+        stream,
+        partial,
+        content: parts.next().unwrap_or(""),
+    })
+}
+
+/// Joins `P` fragments with their closing `F` line, per stream
+#[derive(Default)]
+struct CriAssembler {
+    pending: HashMap<String, (DateTime<Utc>, String)>,
+}
+
+impl CriAssembler {
+    /// Returns (timestamp of the first fragment, stream, full message) once a line is complete
+    fn push(&mut self, line: CriLine<'_>) -> Option<(DateTime<Utc>, String, String)> {
+        let (timestamp, mut message) = self
+            .pending
+            .remove(line.stream)
+            .unwrap_or_else(|| (line.timestamp, String::new()));
+        message.push_str(line.content);
+
+        if line.partial && message.len() < MAX_PARTIAL_SIZE {
+            self.pending.insert(line.stream.to_string(), (timestamp, message));
+            return None;
+        }
+        Some((timestamp, line.stream.to_string(), message))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Pod details from the kubelet that are not encoded in the log path
+#[derive(Debug, Default)]
+struct PodMetadata {
+    node_name: Option<String>,
+    labels: BTreeMap<String, String>,
+    images: HashMap<String, String>,
+}
+
+type PodCache = Arc<RwLock<HashMap<String, Arc<PodMetadata>>>>;
+
+#[derive(Deserialize)]
+struct PodList {
+    #[serde(default)]
+    items: Vec<Pod>,
+}
+
+#[derive(Deserialize)]
+struct Pod {
+    metadata: PodObjectMeta,
+    #[serde(default)]
+    spec: PodSpec,
+}
+
+#[derive(Deserialize)]
+struct PodObjectMeta {
+    #[serde(default)]
+    uid: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+struct PodSpec {
+    #[serde(rename = "nodeName")]
+    node_name: Option<String>,
+    #[serde(default)]
+    containers: Vec<ContainerSpec>,
+}
+
+#[derive(Deserialize)]
+struct ContainerSpec {
+    name: String,
+    #[serde(default)]
+    image: String,
+}
+
+/// Index a kubelet `/pods` response by pod uid
+fn parse_pod_list(data: &[u8]) -> Result<HashMap<String, Arc<PodMetadata>>> {
+    let list: PodList = serde_json::from_slice(data).context("Invalid kubelet pod list")?;
+
+    Ok(list
+        .items
+        .into_iter()
+        .filter(|pod| !pod.metadata.uid.is_empty())
+        .map(|pod| {
+            let metadata = PodMetadata {
+                node_name: pod.spec.node_name,
+                labels: pod.metadata.labels,
+                images: pod
+                    .spec
+                    .containers
+                    .into_iter()
+                    .map(|container| (container.name, container.image))
+                    .collect(),
+            };
+            (pod.metadata.uid, Arc::new(metadata))
+        })
+        .collect())
+}
+
+async fn fetch_pods(client: &reqwest::Client, config: &KubernetesSource, url: &str) -> Result<HashMap<String, Arc<PodMetadata>>> {
+    let mut request = client.get(format!("{}/pods", url.trim_end_matches('/')));
+    match tokio::fs::read_to_string(&config.kubelet_token_file).await {
+        Ok(token) => request = request.bearer_auth(token.trim()),
+        Err(e) => debug!("No kubelet token at {}: {}", config.kubelet_token_file.display(), e),
+    }
+
+    let response = request.send().await?.error_for_status()?;
+    parse_pod_list(&response.bytes().await?)
+}
+
+fn kubelet_refresher(
+    config: KubernetesSource,
+    url: String,
+    pods: PodCache,
+) -> Result<impl std::future::Future<Output = ()>> {
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(config.kubelet_insecure)
+        .timeout(Duration::from_secs(10))
+        .build()
+        .context("Failed to build kubelet client")?;
+
+    Ok(async move {
+        let mut ticker = tokio::time::interval(KUBELET_REFRESH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            match fetch_pods(&client, &config, &url).await {
+                Ok(list) => *pods.write().unwrap() = list,
+                Err(e) => warn!("Failed to refresh pod list from kubelet: {:#}", e),
+            }
+        }
+    })
+}
+
+#[derive(Clone)]
+struct TailContext {
+    severity: Arc<SeverityMapper>,
+    pods: PodCache,
+    state: Arc<StateStore>,
+    poll_interval: Duration,
+    tx: mpsc::Sender<LogEntry>,
+}
+
+impl TailContext {
+    fn entry(&self, path: &Path, pod: &PodLogPath, timestamp: DateTime<Utc>, stream: &str, message: String) -> LogEntry {
+        let mut resource = serde_json::Map::new();
+        resource.insert("k8s.namespace.name".to_string(), pod.namespace.as_str().into());
+        resource.insert("k8s.pod.name".to_string(), pod.pod.as_str().into());
+        resource.insert("k8s.pod.uid".to_string(), pod.uid.as_str().into());
+        resource.insert("k8s.container.name".to_string(), pod.container.as_str().into());
+        resource.insert("k8s.container.restart_count".to_string(), pod.restart_count.into());
+
+        if let Some(metadata) = self.pods.read().unwrap().get(&pod.uid) {
+            if let Some(node) = &metadata.node_name {
+                resource.insert("k8s.node.name".to_string(), node.as_str().into());
+            }
+            if let Some(image) = metadata.images.get(&pod.container) {
+                resource.insert("container.image.name".to_string(), image.as_str().into());
+            }
+            for (key, value) in &metadata.labels {
+                resource.insert(format!("k8s.pod.label.{}", key), value.as_str().into());
+            }
+        }
+
+        LogEntry {
+            timestamp,
+            observed_timestamp: Utc::now(),
+            severity: self.severity.detect(&message).unwrap_or(Severity::INFO),
+            service: pod.container.clone(),
+            message,
+            body: None,
+            attributes: Some(serde_json::json!({
+                "source_type": "kubernetes",
+                "log.iostream": stream,
+                "log.file.path": path.to_string_lossy(),
+            })),
+            trace: None,
+            scope: None,
+            resource: Some(resource),
+        }
+    }
+}
+
+/// Follow one container log file across kubelet rotations until it is removed
+async fn tail_pod_log(path: PathBuf, pod: PodLogPath, context: TailContext, from_start: bool) -> Result<()> {
+    let key = path.to_string_lossy().into_owned();
+    let Some((mut inode, len)) = file_identity(&path) else {
+        return Ok(());
+    };
+
+    let mut offset = saved_offset(&context.state, &key, inode).unwrap_or(if from_start { 0 } else { len });
+    info!("Tailing {}/{} container {} from offset {}", pod.namespace, pod.pod, pod.container, offset);
+
+    let mut file = File::open(&path).await.with_context(|| format!("Failed to open {}", path.display()))?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    let mut reader = BufReader::new(file);
+    let mut assembler = CriAssembler::default();
+    // Offset of the first unfinished `P` fragment, so a restart re-reads the whole line
+    let mut committed = offset;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line).await?;
+
+        if read > 0 && line.ends_with('\n') {
+            offset += read as u64;
+            match parse_cri_line(&line) {
+                Some(cri) => {
+                    if let Some((timestamp, stream, message)) = assembler.push(cri) {
+                        let entry = context.entry(&path, &pod, timestamp, &stream, message);
+                        if context.tx.send(entry).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                }
+                None => warn!("Skipping malformed CRI line in {}", path.display()),
+            }
+            if assembler.is_empty() {
+                committed = offset;
+                context.state.set(&key, format!("{}:{}", inode, committed));
+            }
+            continue;
+        }
+
+        if read > 0 {
+            // Incomplete line, wait for the writer to finish it
+            reader.seek(std::io::SeekFrom::Start(offset)).await?;
+        }
+
+        match file_identity(&path) {
+            None => {
+                info!("Log file removed: {}", path.display());
+                context.state.remove(&key);
+                return Ok(());
+            }
+            Some((current_inode, current_len)) if current_inode != inode || current_len < committed => {
+                debug!("Log file rotated: {}", path.display());
+                let file = File::open(&path).await.with_context(|| format!("Failed to reopen {}", path.display()))?;
+                reader = BufReader::new(file);
+                inode = current_inode;
+                offset = 0;
+                committed = 0;
+                assembler = CriAssembler::default();
+                context.state.set(&key, format!("{}:0", inode));
+                continue;
+            }
+            Some(_) => {}
+        }
+
+        tokio::time::sleep(context.poll_interval).await;
+    }
+}
+
+/// Container log files under `log_dir` that pass the namespace filters
+fn discover(config: &KubernetesSource) -> Vec<(PathBuf, PodLogPath)> {
+    let pattern = format!("{}/*/*/*.log", config.log_dir.display());
+    let paths = match glob::glob(&pattern) {
+        Ok(paths) => paths,
+        Err(e) => {
+            error!("Invalid pod log pattern {}: {}", pattern, e);
+            return vec![];
+        }
+    };
+
+    paths
+        .flatten()
+        .filter_map(|path| parse_pod_path(&path).map(|pod| (path, pod)))
+        .filter(|(_, pod)| config.namespaces.is_empty() || config.namespaces.contains(&pod.namespace))
+        .filter(|(_, pod)| !config.exclude_namespaces.contains(&pod.namespace))
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl LogProvider for KubernetesProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let k8s_config = match &self.config.sources.kubernetes {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("Kubernetes provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        info!("Starting kubernetes provider for {}", k8s_config.log_dir.display());
+
+        // Dropped with this future, so stopping the provider stops the
+        // refresher and the tails
+        let mut tasks = JoinSet::new();
+        let pods: PodCache = Arc::default();
+        if let Some(url) = k8s_config.kubelet_url.clone() {
+            tasks.spawn(kubelet_refresher(k8s_config.clone(), url, pods.clone())?);
+        }
+
+        tasks.spawn(self.state.clone().flush_every(Duration::from_secs(1)));
+
+        let context = TailContext {
+            severity: Arc::new(SeverityMapper::from_config(&self.config.severity)?),
+            pods,
+            state: self.state.clone(),
+            poll_interval: Duration::from_millis(250),
+            tx,
+        };
+
+        let mut tailing: HashMap<PathBuf, AbortHandle> = HashMap::new();
+        let mut initial_scan = true;
+
+        loop {
+            while tasks.try_join_next().is_some() {}
+            tailing.retain(|_, handle| !handle.is_finished());
+
+            for (path, pod) in discover(&k8s_config) {
+                if tailing.contains_key(&path) {
+                    continue;
+                }
+                // Files present at startup are tailed from the end (or saved offset),
+                // containers that appear later are read from the beginning
+                let from_start = !initial_scan;
+                let context = context.clone();
+                let tail_path = path.clone();
+                let handle = tasks.spawn(async move {
+                    if let Err(e) = tail_pod_log(tail_path.clone(), pod, context, from_start).await {
+                        error!("Error tailing {}: {:#}", tail_path.display(), e);
+                    }
+                });
+                tailing.insert(path, handle);
+            }
+            initial_scan = false;
+
+            tokio::select! {
+                _ = tokio::time::sleep(DISCOVERY_INTERVAL) => {}
+                _ = context.tx.closed() => return Ok(()),
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "kubernetes"
+    }
+
+    fn state(&self) -> Option<&StateStore> {
+        Some(&self.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn fixture_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ilog-k8s-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn source(log_dir: &Path) -> KubernetesSource {
+        KubernetesSource {
+            enabled: true,
+            log_dir: log_dir.to_path_buf(),
+            namespaces: vec![],
+            exclude_namespaces: vec!["kube-system".to_string()],
+            kubelet_url: None,
+            kubelet_token_file: PathBuf::from("/nonexistent"),
+            kubelet_insecure: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_pod_path() {
+        let pod = parse_pod_path(Path::new(
+            "/var/log/pods/shop_checkout-7d9f_0d6c1f7e-1c1a-4b8e-9d1e-5f0a2b3c4d5e/api/2.log",
+        ))
+        .unwrap();
+        assert_eq!(pod.namespace, "shop");
+        assert_eq!(pod.pod, "checkout-7d9f");
+        assert_eq!(pod.uid, "0d6c1f7e-1c1a-4b8e-9d1e-5f0a2b3c4d5e");
+        assert_eq!(pod.container, "api");
+        assert_eq!(pod.restart_count, 2);
+
+        assert_eq!(parse_pod_path(Path::new("/var/log/pods/broken/api/0.log")), None);
+        assert_eq!(parse_pod_path(Path::new("/var/log/pods/a_b_c/api/0.log.20240101-000000")), None);
+    }
+
+    #[test]
+    fn test_cri_reassembly() {
+        let mut assembler = CriAssembler::default();
+        let lines = [
+            "2024-05-01T10:00:00.000000001Z stdout P hello ",
+            "2024-05-01T10:00:00.500000000Z stderr F boom",
+            "2024-05-01T10:00:01.000000000Z stdout P big ",
+            "2024-05-01T10:00:02.000000000Z stdout F world",
+            "2024-05-01T10:00:03.000000000Z stdout F",
+        ];
+
+        let mut complete = vec![];
+        for line in lines {
+            if let Some(done) = assembler.push(parse_cri_line(line).unwrap()) {
+                complete.push(done);
+            }
+        }
+
+        assert_eq!(complete.len(), 3);
+        assert_eq!((complete[0].1.as_str(), complete[0].2.as_str()), ("stderr", "boom"));
+        assert_eq!(complete[1].2, "hello big world");
+        assert_eq!(complete[1].0.timestamp_subsec_nanos(), 1);
+        assert_eq!(complete[2].2, "");
+        assert!(assembler.is_empty());
+
+        assert_eq!(parse_cri_line("not a cri line"), None);
+        assert_eq!(parse_cri_line("2024-05-01T10:00:00Z stdin F x"), None);
+    }
+
+    #[test]
+    fn test_parse_pod_list() {
+        let pods = parse_pod_list(br#"{"items": [{
+            "metadata": {"name": "checkout-7d9f", "uid": "abc", "labels": {"app": "checkout"}},
+            "spec": {"nodeName": "node-1", "containers": [{"name": "api", "image": "shop/api:1.4"}]}
+        }]}"#)
+        .unwrap();
+        let pod = &pods["abc"];
+        assert_eq!(pod.node_name.as_deref(), Some("node-1"));
+        assert_eq!(pod.labels["app"], "checkout");
+        assert_eq!(pod.images["api"], "shop/api:1.4");
+    }
+
+    #[tokio::test]
+    async fn test_discover_and_tail_fixture() {
+        let dir = fixture_dir("tail");
+        let container_dir = dir.join("shop_checkout-7d9f_abc").join("api");
+        std::fs::create_dir_all(&container_dir).unwrap();
+        std::fs::create_dir_all(dir.join("kube-system_coredns-1_def").join("coredns")).unwrap();
+        std::fs::write(dir.join("kube-system_coredns-1_def/coredns/0.log"), "").unwrap();
+
+        let log_path = container_dir.join("0.log");
+        let mut file = std::fs::File::create(&log_path).unwrap();
+        writeln!(file, "2024-05-01T10:00:00Z stdout P [ERROR] payment ").unwrap();
+        writeln!(file, "2024-05-01T10:00:00Z stdout F declined").unwrap();
+        writeln!(file, "2024-05-01T10:00:01Z stderr F retrying").unwrap();
+
+        let discovered = discover(&source(&dir));
+        assert_eq!(discovered.len(), 1);
+        let (path, pod) = discovered.into_iter().next().unwrap();
+
+        let mut metadata = PodMetadata::default();
+        metadata.labels.insert("app".to_string(), "checkout".to_string());
+        let pods: PodCache = Arc::default();
+        pods.write().unwrap().insert("abc".to_string(), Arc::new(metadata));
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let state = StateStore::open(&dir.join("state"), "kubernetes");
+        let context = TailContext {
+            severity: Arc::new(SeverityMapper::from_config(&Default::default()).unwrap()),
+            pods,
+            state: state.clone(),
+            poll_interval: Duration::from_millis(10),
+            tx,
+        };
+        let mut tails = JoinSet::new();
+        tails.spawn(tail_pod_log(path.clone(), pod, context, true));
+
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.message, "[ERROR] payment declined");
+        assert_eq!(first.severity, Severity::ERROR);
+        assert_eq!(first.service, "api");
+        let resource = first.resource.unwrap();
+        assert_eq!(resource["k8s.namespace.name"], "shop");
+        assert_eq!(resource["k8s.pod.label.app"], "checkout");
+        assert_eq!(first.attributes.unwrap()["log.iostream"], "stdout");
+
+        // Lines appended later are picked up
+        writeln!(file, "2024-05-01T10:00:02Z stdout F done").unwrap();
+        assert_eq!(rx.recv().await.unwrap().message, "retrying");
+        assert_eq!(rx.recv().await.unwrap().message, "done");
+
+        let (inode, len) = file_identity(&path).unwrap();
+        let expected = Some(format!("{}:{}", inode, len));
+        for _ in 0..100 {
+            if state.get(&path.to_string_lossy()) == expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(state.get(&path.to_string_lossy()), expected);
+
+        // Removing the file ends the tail
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), tails.join_next()).await.unwrap().unwrap().unwrap().unwrap();
+        assert_eq!(state.get(&path.to_string_lossy()), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
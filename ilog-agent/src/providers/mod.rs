@@ -1,5 +1,10 @@
 use tokio::sync::mpsc;
 use anyhow::Result;
+use std::sync::Arc;
+use crate::config::{AgentConfig, Sources};
+use crate::health::Health;
+#[cfg(any(feature = "file", feature = "docker", feature = "journald", feature = "kubernetes"))]
+use crate::state::StateStore;
 use crate::tcp_sender::LogEntry;
 
 /// Provider trait - each log source implements this
@@ -10,6 +15,114 @@ pub trait LogProvider: Send + Sync {
     
     /// Provider name for logging/debugging
     fn name(&self) -> &str;
+
+    /// Validate the configuration against the host without collecting anything;
+    /// returns notes for `check-config` (e.g. what a glob currently matches)
+    async fn check(&self) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Resume positions the provider keeps; the supervisor saves them once
+    /// the provider has stopped
+    #[cfg(any(feature = "file", feature = "docker", feature = "journald", feature = "kubernetes"))]
+    fn state(&self) -> Option<&StateStore> {
+        None
+    }
+}
+
+/// A provider compiled into this build, keyed by its `[sources.<name>]` section
+pub struct Source {
+    pub name: &'static str,
+    enabled: fn(&Sources) -> bool,
+    same: fn(&Sources, &Sources) -> bool,
+    build: fn(Arc<AgentConfig>, Arc<Health>) -> Box<dyn LogProvider>,
+}
+
+impl Source {
+    pub fn enabled(&self, config: &AgentConfig) -> bool {
+        (self.enabled)(&config.sources)
+    }
+
+    /// Whether a running provider has to be restarted to pick up `new`
+    pub fn changed(&self, old: &AgentConfig, new: &AgentConfig) -> bool {
+        !(self.same)(&old.sources, &new.sources)
+            || old.severity != new.severity
+            || old.agent.state_dir != new.agent.state_dir
+    }
+
+    /// `health` receives the state of the provider's own tailers, if it supervises any
+    pub fn build(&self, config: Arc<AgentConfig>, health: Arc<Health>) -> Box<dyn LogProvider> {
+        (self.build)(config, health)
+    }
+}
+
+/// Every provider compiled into this build
+pub fn sources() -> Vec<Source> {
+    vec![
+        #[cfg(feature = "file")]
+        Source {
+            name: "file",
+            enabled: |s| s.file.as_ref().is_some_and(|f| f.enabled),
+            same: |a, b| a.file == b.file,
+            build: |config, health| Box::new(file::FileProvider::new(config, health)),
+        },
+        #[cfg(feature = "docker")]
+        Source {
+            name: "docker",
+            enabled: |s| s.docker.as_ref().is_some_and(|d| d.enabled),
+            same: |a, b| a.docker == b.docker,
+            build: |config, _| Box::new(docker::DockerProvider::new(config)),
+        },
+        #[cfg(all(feature = "journald", target_os = "linux"))]
+        Source {
+            name: "journald",
+            enabled: |s| s.journald.as_ref().is_some_and(|j| j.enabled),
+            same: |a, b| a.journald == b.journald,
+            build: |config, _| Box::new(systemd::SystemdProvider::new(config)),
+        },
+        #[cfg(all(feature = "syslog", unix))]
+        Source {
+            name: "syslog",
+            enabled: |s| s.syslog.as_ref().is_some_and(|s| s.enabled),
+            same: |a, b| a.syslog == b.syslog,
+            build: |config, _| Box::new(syslog::SyslogProvider::new(config)),
+        },
+        #[cfg(feature = "otlp")]
+        Source {
+            name: "otlp",
+            enabled: |s| s.otlp.as_ref().is_some_and(|o| o.enabled),
+            same: |a, b| a.otlp == b.otlp,
+            build: |config, _| Box::new(otlp::OtlpProvider::new(config)),
+        },
+        #[cfg(all(feature = "kubernetes", unix))]
+        Source {
+            name: "kubernetes",
+            enabled: |s| s.kubernetes.as_ref().is_some_and(|k| k.enabled),
+            same: |a, b| a.kubernetes == b.kubernetes,
+            build: |config, _| Box::new(kubernetes::KubernetesProvider::new(config)),
+        },
+        #[cfg(feature = "exec")]
+        Source {
+            name: "exec",
+            enabled: |s| s.exec.as_ref().is_some_and(|e| e.enabled),
+            same: |a, b| a.exec == b.exec,
+            build: |config, _| Box::new(exec::ExecProvider::new(config)),
+        },
+        #[cfg(all(feature = "socket", unix))]
+        Source {
+            name: "socket",
+            enabled: |s| s.socket.as_ref().is_some_and(|s| s.enabled),
+            same: |a, b| a.socket == b.socket,
+            build: |config, _| Box::new(socket::SocketProvider::new(config)),
+        },
+        #[cfg(feature = "http-server")]
+        Source {
+            name: "http",
+            enabled: |s| s.http.as_ref().is_some_and(|h| h.enabled),
+            same: |a, b| a.http == b.http,
+            build: |config, _| Box::new(http::HttpProvider::new(config)),
+        },
+    ]
 }
 
 #[cfg(feature = "file")]
@@ -21,6 +134,20 @@ pub mod docker;
 #[cfg(all(feature = "journald", target_os = "linux"))]
 pub mod systemd;
 
-// Future providers
-// pub mod kubernetes;
-// pub mod http;
+#[cfg(all(feature = "syslog", unix))]
+pub mod syslog;
+
+#[cfg(feature = "otlp")]
+pub mod otlp;
+
+#[cfg(all(feature = "kubernetes", unix))]
+pub mod kubernetes;
+
+#[cfg(feature = "exec")]
+pub mod exec;
+
+#[cfg(all(feature = "socket", unix))]
+pub mod socket;
+
+#[cfg(feature = "http-server")]
+pub mod http;
@@ -0,0 +1,341 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::{LogsService, LogsServiceServer};
+use opentelemetry_proto::tonic::collector::logs::v1::{ExportLogsServiceRequest, ExportLogsServiceResponse};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue};
+use prost::Message;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::AgentConfig;
+use crate::severity::Severity;
+use crate::tcp_sender::{LogEntry, Scope, TraceContext};
+use super::LogProvider;
+
+pub struct OtlpProvider {
+    config: Arc<AgentConfig>,
+}
+
+impl OtlpProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        Self { config }
+    }
+}
+
+fn any_value_to_json(value: &AnyValue) -> Value {
+    match &value.value {
+        Some(any_value::Value::StringValue(s)) => Value::String(s.clone()),
+        Some(any_value::Value::BoolValue(b)) => Value::Bool(*b),
+        Some(any_value::Value::IntValue(i)) => Value::from(*i),
+        Some(any_value::Value::DoubleValue(d)) => Value::from(*d),
+        Some(any_value::Value::ArrayValue(array)) => {
+            Value::Array(array.values.iter().map(any_value_to_json).collect())
+        }
+        Some(any_value::Value::KvlistValue(list)) => {
+            let mut map = Map::new();
+            insert_key_values(&mut map, "", &list.values);
+            Value::Object(map)
+        }
+        Some(any_value::Value::BytesValue(bytes)) => Value::String(hex(bytes)),
+        None => Value::Null,
+    }
+}
+
+fn insert_key_values(map: &mut Map<String, Value>, prefix: &str, attributes: &[KeyValue]) {
+    for attribute in attributes {
+        let value = attribute.value.as_ref().map(any_value_to_json).unwrap_or(Value::Null);
+        map.insert(format!("{}{}", prefix, attribute.key), value);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Trace and span ids are only meaningful at their exact length and when not all zero
+fn valid_id(id: &[u8], len: usize) -> Option<String> {
+    (id.len() == len && id.iter().any(|b| *b != 0)).then(|| hex(id))
+}
+
+fn timestamp_from_nanos(nanos: u64) -> Option<DateTime<Utc>> {
+    (nanos > 0).then(|| DateTime::from_timestamp_nanos(nanos as i64))
+}
+
+/// Flatten an export request into agent log entries
+fn convert(request: ExportLogsServiceRequest) -> Vec<LogEntry> {
+    let mut entries = Vec::new();
+
+    for resource_logs in request.resource_logs {
+        let resource_attributes = resource_logs
+            .resource
+            .map(|resource| resource.attributes)
+            .unwrap_or_default();
+        let service = resource_attributes
+            .iter()
+            .find(|kv| kv.key == "service.name")
+            .and_then(|kv| kv.value.as_ref())
+            .and_then(|value| match &value.value {
+                Some(any_value::Value::StringValue(s)) if !s.is_empty() => Some(s.clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| "otlp".to_string());
+
+        let mut resource = Map::new();
+        insert_key_values(&mut resource, "", &resource_attributes);
+
+        for scope_logs in resource_logs.scope_logs {
+            let scope = scope_logs.scope.filter(|scope| !scope.name.is_empty()).map(|scope| {
+                let mut attributes = Map::new();
+                insert_key_values(&mut attributes, "", &scope.attributes);
+                Scope {
+                    name: scope.name,
+                    version: Some(scope.version).filter(|version| !version.is_empty()),
+                    attributes: Some(attributes).filter(|attributes| !attributes.is_empty()),
+                }
+            });
+
+            for record in scope_logs.log_records {
+                let mut attributes = Map::new();
+                attributes.insert("source_type".to_string(), "otlp".into());
+                insert_key_values(&mut attributes, "", &record.attributes);
+                let trace = valid_id(&record.trace_id, 16).map(|trace_id| TraceContext {
+                    trace_id,
+                    span_id: valid_id(&record.span_id, 8),
+                    // The low byte holds the W3C trace flags
+                    trace_flags: Some((record.flags & 0xff) as u8),
+                });
+
+                let severity = Severity::from_number(record.severity_number)
+                    .or_else(|| Severity::parse(&record.severity_text))
+                    .unwrap_or(Severity::INFO);
+
+                // Structured bodies are kept as sent, with their JSON text as the message
+                let (message, body) = match record.body.as_ref().map(any_value_to_json) {
+                    Some(Value::String(s)) => (s, None),
+                    Some(Value::Null) | None => (String::new(), None),
+                    Some(other) => (other.to_string(), Some(other)),
+                };
+                let observed_timestamp = timestamp_from_nanos(record.observed_time_unix_nano).unwrap_or_else(Utc::now);
+
+                entries.push(LogEntry {
+                    timestamp: timestamp_from_nanos(record.time_unix_nano).unwrap_or(observed_timestamp),
+                    observed_timestamp,
+                    severity,
+                    service: service.clone(),
+                    message,
+                    body,
+                    attributes: Some(Value::Object(attributes)),
+                    trace,
+                    scope: scope.clone(),
+                    resource: Some(resource.clone()),
+                });
+            }
+        }
+    }
+
+    entries
+}
+
+async fn forward(tx: &mpsc::Sender<LogEntry>, request: ExportLogsServiceRequest) -> Result<(), mpsc::error::SendError<LogEntry>> {
+    for entry in convert(request) {
+        tx.send(entry).await?;
+    }
+    Ok(())
+}
+
+struct GrpcReceiver {
+    tx: mpsc::Sender<LogEntry>,
+}
+
+#[tonic::async_trait]
+impl LogsService for GrpcReceiver {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportLogsServiceRequest>,
+    ) -> Result<tonic::Response<ExportLogsServiceResponse>, tonic::Status> {
+        forward(&self.tx, request.into_inner())
+            .await
+            .map_err(|_| tonic::Status::unavailable("agent is shutting down"))?;
+        Ok(tonic::Response::new(ExportLogsServiceResponse { partial_success: None }))
+    }
+}
+
+/// OTLP/HTTP `POST /v1/logs`, answering in the request's encoding
+async fn export_http(State(tx): State<mpsc::Sender<LogEntry>>, headers: HeaderMap, body: Bytes) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/x-protobuf")
+        .to_string();
+    let is_json = content_type.starts_with("application/json");
+
+    if headers.contains_key(header::CONTENT_ENCODING) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "compressed payloads are not supported").into_response();
+    }
+
+    let request = if is_json {
+        serde_json::from_slice::<ExportLogsServiceRequest>(&body).map_err(|e| e.to_string())
+    } else if content_type.starts_with("application/x-protobuf") {
+        ExportLogsServiceRequest::decode(body).map_err(|e| e.to_string())
+    } else {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "expected application/json or application/x-protobuf").into_response();
+    };
+
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    if forward(&tx, request).await.is_err() {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    }
+
+    let response = ExportLogsServiceResponse { partial_success: None };
+    if is_json {
+        ([(header::CONTENT_TYPE, "application/json")], "{}").into_response()
+    } else {
+        ([(header::CONTENT_TYPE, "application/x-protobuf")], response.encode_to_vec()).into_response()
+    }
+}
+
+async fn serve_http(addr: String, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind OTLP/HTTP listener {}", addr))?;
+    info!("OTLP/HTTP listening on http://{}/v1/logs", addr);
+
+    let app = Router::new().route("/v1/logs", post(export_http)).with_state(tx);
+    axum::serve(listener, app).await.context("OTLP/HTTP server failed")
+}
+
+async fn serve_grpc(addr: String, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    let socket_addr = addr
+        .parse()
+        .with_context(|| format!("sources.otlp.grpc: invalid address '{}'", addr))?;
+    info!("OTLP/gRPC listening on {}", addr);
+
+    tonic::transport::Server::builder()
+        .add_service(LogsServiceServer::new(GrpcReceiver { tx }))
+        .serve(socket_addr)
+        .await
+        .context("OTLP/gRPC server failed")
+}
+
+#[async_trait::async_trait]
+impl LogProvider for OtlpProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let otlp_config = match &self.config.sources.otlp {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("OTLP provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        // Dropped with this future, so stopping the provider closes the listeners
+        let mut listeners = JoinSet::new();
+        if let Some(addr) = otlp_config.http {
+            listeners.spawn(serve_http(addr, tx.clone()));
+        }
+        if let Some(addr) = otlp_config.grpc {
+            listeners.spawn(serve_grpc(addr, tx.clone()));
+        }
+
+        if listeners.is_empty() {
+            warn!("OTLP provider has no http or grpc listener configured");
+        }
+
+        // A failed listener fails the provider, so the supervisor restarts it;
+        // a closed channel means the supervisor is stopping us
+        loop {
+            tokio::select! {
+                result = listeners.join_next() => match result {
+                    Some(result) => result??,
+                    None => break,
+                },
+                _ = tx.closed() => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "otlp"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &str = r#"{
+        "resourceLogs": [{
+            "resource": {"attributes": [
+                {"key": "service.name", "value": {"stringValue": "checkout"}},
+                {"key": "host.name", "value": {"stringValue": "web-1"}}
+            ]},
+            "scopeLogs": [{
+                "scope": {"name": "app.orders", "version": "1.2.0"},
+                "logRecords": [{
+                    "timeUnixNano": "1700000000000000000",
+                    "severityNumber": 17,
+                    "severityText": "Error",
+                    "body": {"stringValue": "payment declined"},
+                    "attributes": [{"key": "order.id", "value": {"intValue": "42"}}],
+                    "traceId": "5b8efff798038103d269b633813fc60c",
+                    "spanId": "eee19b7ec3c1b174",
+                    "flags": 1
+                }, {
+                    "severityText": "warn",
+                    "body": {"kvlistValue": {"values": [{"key": "retry", "value": {"boolValue": true}}]}},
+                    "traceId": "00000000000000000000000000000000"
+                }]
+            }]
+        }]
+    }"#;
+
+    #[test]
+    fn test_convert_json() {
+        let request: ExportLogsServiceRequest = serde_json::from_str(REQUEST).unwrap();
+        let entries = convert(request);
+        assert_eq!(entries.len(), 2);
+
+        let entry = &entries[0];
+        assert_eq!(entry.service, "checkout");
+        assert_eq!(entry.severity, Severity::ERROR);
+        assert_eq!(entry.message, "payment declined");
+        assert_eq!(entry.timestamp.timestamp(), 1_700_000_000);
+        let trace = entry.trace.as_ref().unwrap();
+        assert_eq!(trace.trace_id, "5b8efff798038103d269b633813fc60c");
+        assert_eq!(trace.span_id.as_deref(), Some("eee19b7ec3c1b174"));
+        assert_eq!(trace.trace_flags, Some(1));
+        assert_eq!(entry.attributes.as_ref().unwrap()["order.id"], 42);
+        assert_eq!(entry.resource.as_ref().unwrap()["host.name"], "web-1");
+        let scope = entry.scope.as_ref().unwrap();
+        assert_eq!((scope.name.as_str(), scope.version.as_deref()), ("app.orders", Some("1.2.0")));
+
+        let entry = &entries[1];
+        assert_eq!(entry.severity, Severity::WARN);
+        assert_eq!(entry.message, r#"{"retry":true}"#);
+        assert_eq!(entry.body, Some(serde_json::json!({ "retry": true })));
+        assert_eq!(entry.to_otlp_json()["body"]["retry"], true);
+        assert!(entry.trace.is_none());
+    }
+
+    #[test]
+    fn test_convert_protobuf() {
+        let request: ExportLogsServiceRequest = serde_json::from_str(REQUEST).unwrap();
+        let decoded = ExportLogsServiceRequest::decode(request.encode_to_vec().as_slice()).unwrap();
+        assert_eq!(convert(decoded).len(), 2);
+    }
+}
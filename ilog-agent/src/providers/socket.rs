@@ -0,0 +1,295 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::task::JoinSet;
+use tracing::{info, warn, debug};
+
+use crate::config::AgentConfig;
+use crate::record::RecordParser;
+use crate::severity::SeverityMapper;
+use crate::tcp_sender::LogEntry;
+use super::LogProvider;
+
+/// Longer lines are cut at this size
+const MAX_LINE_SIZE: u64 = 1024 * 1024;
+
+pub struct SocketProvider {
+    config: Arc<AgentConfig>,
+}
+
+impl SocketProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        Self { config }
+    }
+}
+
+/// Octal permission string such as "0660"
+fn parse_mode(mode: &str) -> Result<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .with_context(|| format!("sources.socket.mode: invalid octal permissions '{}'", mode))
+}
+
+/// Forward every line of one writer until it disconnects
+async fn read_lines<R: AsyncRead + Unpin>(reader: R, parser: &RecordParser, tx: &mpsc::Sender<LogEntry>) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        if (&mut reader).take(MAX_LINE_SIZE).read_until(b'\n', &mut line).await? == 0 {
+            return Ok(());
+        }
+
+        if let Some(entry) = parser.parse_line(&String::from_utf8_lossy(&line)) {
+            if tx.send(entry).await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn serve_socket(path: PathBuf, mode: u32, parser: Arc<RecordParser>, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Remove a stale socket left behind by a previous run, but nothing else
+    match std::fs::symlink_metadata(&path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to inspect {}", path.display())),
+    }
+    let listener = UnixListener::bind(&path)
+        .with_context(|| format!("Failed to bind socket {}", path.display()))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+    info!("Listening for log lines on unix://{} (mode {:o})", path.display(), mode);
+
+    // Writers are disconnected with the listener, e.g. when the provider is reloaded
+    let mut writers = JoinSet::new();
+    loop {
+        let (stream, _) = listener.accept().await?;
+        while writers.try_join_next().is_some() {}
+        let parser = parser.clone();
+        let tx = tx.clone();
+
+        writers.spawn(async move {
+            if let Err(e) = read_lines(stream, &parser, &tx).await {
+                debug!("Socket writer disconnected: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+async fn serve_fifo(path: PathBuf, mode: u32, parser: Arc<RecordParser>, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileTypeExt;
+
+    match std::fs::metadata(&path) {
+        Ok(meta) if meta.file_type().is_fifo() => {}
+        Ok(_) => anyhow::bail!("{} exists and is not a FIFO", path.display()),
+        Err(_) => {
+            let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+            // SAFETY: c_path is a valid NUL-terminated path
+            if unsafe { libc::mkfifo(c_path.as_ptr(), mode as libc::mode_t) } != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to create FIFO {}", path.display()));
+            }
+        }
+    }
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))?;
+
+    // Opening read-write keeps the pipe open while no writer is connected
+    let receiver = tokio::net::unix::pipe::OpenOptions::new()
+        .read_write(true)
+        .open_receiver(&path)
+        .with_context(|| format!("Failed to open FIFO {}", path.display()))?;
+    info!("Reading log lines from FIFO {}", path.display());
+
+    read_lines(receiver, &parser, &tx).await
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn serve_fifo(path: PathBuf, _mode: u32, _parser: Arc<RecordParser>, _tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    anyhow::bail!("FIFO input {} is only supported on Linux", path.display())
+}
+
+#[async_trait::async_trait]
+impl LogProvider for SocketProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let socket_config = match &self.config.sources.socket {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("Socket provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        let mode = parse_mode(&socket_config.mode)?;
+        let parser = Arc::new(RecordParser::new(
+            socket_config.service.clone(),
+            "socket",
+            SeverityMapper::from_config(&self.config.severity)?,
+        ));
+
+        // Dropped with this future, so stopping the provider closes the listeners
+        let mut listeners = JoinSet::new();
+        if let Some(path) = socket_config.path {
+            listeners.spawn(serve_socket(path, mode, parser.clone(), tx.clone()));
+        }
+        if let Some(path) = socket_config.fifo {
+            listeners.spawn(serve_fifo(path, mode, parser.clone(), tx.clone()));
+        }
+
+        if listeners.is_empty() {
+            warn!("Socket provider has no path or fifo configured");
+        }
+
+        // A failed listener fails the provider, so the supervisor restarts it;
+        // a closed channel means the supervisor is stopping us
+        loop {
+            tokio::select! {
+                result = listeners.join_next() => match result {
+                    Some(result) => result??,
+                    None => break,
+                },
+                _ = tx.closed() => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "socket"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use std::path::Path;
+    use tokio::net::UnixStream;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ilog-{}-{}", name, std::process::id()))
+    }
+
+    fn parser() -> Arc<RecordParser> {
+        Arc::new(RecordParser::new("app", "socket", SeverityMapper::from_config(&Default::default()).unwrap()))
+    }
+
+    async fn wait_for(path: &Path) {
+        for _ in 0..100 {
+            if path.exists() {
+                return;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("{} was never created", path.display());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("0660").unwrap(), 0o660);
+        assert_eq!(parse_mode("777").unwrap(), 0o777);
+        assert!(parse_mode("0999").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_socket_concurrent_writers() {
+        let path = test_path("socket");
+        let (tx, mut rx) = mpsc::channel(10);
+        let server = tokio::spawn(serve_socket(path.clone(), 0o600, parser(), tx));
+        wait_for(&path).await;
+
+        let mut first = UnixStream::connect(&path).await.unwrap();
+        let mut second = UnixStream::connect(&path).await.unwrap();
+        first.write_all(b"{\"message\":\"from json\",\"level\":\"error\"}\n").await.unwrap();
+        second.write_all(b"plain text line\n").await.unwrap();
+
+        let mut messages = [rx.recv().await.unwrap(), rx.recv().await.unwrap()];
+        messages.sort_by(|a, b| a.message.cmp(&b.message));
+        assert_eq!(messages[0].message, "from json");
+        assert_eq!(messages[0].severity, crate::severity::Severity::ERROR);
+        assert_eq!(messages[1].message, "plain text line");
+        assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        server.abort();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_socket_replaces_only_stale_sockets() {
+        let path = test_path("stale");
+        let _ = std::fs::remove_file(&path);
+
+        // A regular file, a directory or a symlink is left alone
+        std::fs::write(&path, "keep me").unwrap();
+        let (tx, mut rx) = mpsc::channel(10);
+        let error = serve_socket(path.clone(), 0o600, parser(), tx.clone()).await.unwrap_err();
+        assert!(error.to_string().contains("is not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::create_dir(&path).unwrap();
+        assert!(serve_socket(path.clone(), 0o600, parser(), tx.clone()).await.is_err());
+        assert!(path.is_dir());
+        std::fs::remove_dir(&path).unwrap();
+
+        std::os::unix::fs::symlink("/nonexistent", &path).unwrap();
+        assert!(serve_socket(path.clone(), 0o600, parser(), tx.clone()).await.is_err());
+        assert!(std::fs::symlink_metadata(&path).unwrap().file_type().is_symlink());
+        std::fs::remove_file(&path).unwrap();
+
+        // A socket left behind by a previous run is replaced
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let server = tokio::spawn(serve_socket(path.clone(), 0o600, parser(), tx));
+        let mut writer = loop {
+            match UnixStream::connect(&path).await {
+                Ok(writer) => break writer,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        writer.write_all(b"after restart\n").await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().message, "after restart");
+        server.abort();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_fifo() {
+        let path = test_path("fifo");
+        let _ = std::fs::remove_file(&path);
+        let (tx, mut rx) = mpsc::channel(10);
+        let server = tokio::spawn(serve_fifo(path.clone(), 0o620, parser(), tx));
+        wait_for(&path).await;
+
+        // Two writers one after the other, the reader survives the first closing
+        for line in ["first\n", "second\n"] {
+            // Opening fails with ENXIO until the reader side is open
+            let mut writer = loop {
+                match tokio::net::unix::pipe::OpenOptions::new().open_sender(&path) {
+                    Ok(writer) => break writer,
+                    Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                }
+            };
+            writer.write_all(line.as_bytes()).await.unwrap();
+        }
+        assert_eq!(rx.recv().await.unwrap().message, "first");
+        assert_eq!(rx.recv().await.unwrap().message, "second");
+
+        server.abort();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,529 @@
+use tokio::sync::mpsc;
+use anyhow::{Result, Context};
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeZone, Utc};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket, UnixDatagram};
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::config::AgentConfig;
+use crate::severity::{Severity, SeverityMapper};
+use crate::tcp_sender::LogEntry;
+use super::LogProvider;
+
+/// Largest accepted syslog message (datagram or TCP frame)
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Longest octet-count prefix read before giving up on a frame, space included
+const MAX_OCTET_COUNT_PREFIX: u64 = 10;
+
+const FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron",
+    "authpriv", "ftp", "ntp", "security", "console", "solaris-cron", "local0", "local1",
+    "local2", "local3", "local4", "local5", "local6", "local7",
+];
+
+pub struct SyslogProvider {
+    config: Arc<AgentConfig>,
+}
+
+impl SyslogProvider {
+    pub fn new(config: Arc<AgentConfig>) -> Self {
+        Self { config }
+    }
+}
+
+/// A parsed RFC 5424 or RFC 3164 message
+#[derive(Debug, Default, PartialEq)]
+struct SyslogMessage {
+    facility: Option<u8>,
+    severity: Option<u8>,
+    timestamp: Option<DateTime<Utc>>,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    proc_id: Option<String>,
+    msg_id: Option<String>,
+    structured_data: Option<String>,
+    message: String,
+}
+
+/// Split off `<PRI>` and return (facility, severity, rest)
+fn parse_priority(input: &str) -> Option<(u8, u8, &str)> {
+    let rest = input.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    if end == 0 || end > 3 {
+        return None;
+    }
+    let pri: u8 = rest[..end].parse().ok()?;
+    if pri > 191 {
+        return None;
+    }
+    Some((pri / 8, pri % 8, &rest[end + 1..]))
+}
+
+/// "-" is the RFC 5424 NILVALUE
+fn nil(value: &str) -> Option<String> {
+    (value != "-" && !value.is_empty()).then(|| value.to_string())
+}
+
+/// Split the next space-delimited header field
+fn next_field(input: &str) -> (&str, &str) {
+    match input.split_once(' ') {
+        Some((field, rest)) => (field, rest),
+        None => (input, ""),
+    }
+}
+
+/// Length of the STRUCTURED-DATA element(s) at the start of `input`
+fn structured_data_len(input: &str) -> usize {
+    let bytes = input.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() && bytes[i] == b'[' {
+        let mut in_quotes = false;
+        i += 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if in_quotes => i += 1,
+                b'"' => in_quotes = !in_quotes,
+                b']' if !in_quotes => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        i += 1;
+    }
+    i.min(bytes.len())
+}
+
+fn parse_rfc5424(input: &str, message: &mut SyslogMessage) {
+    let (timestamp, rest) = next_field(input);
+    let (hostname, rest) = next_field(rest);
+    let (app_name, rest) = next_field(rest);
+    let (proc_id, rest) = next_field(rest);
+    let (msg_id, rest) = next_field(rest);
+
+    message.timestamp = DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|ts| ts.with_timezone(&Utc));
+    message.hostname = nil(hostname);
+    message.app_name = nil(app_name);
+    message.proc_id = nil(proc_id);
+    message.msg_id = nil(msg_id);
+
+    let rest = if let Some(rest) = rest.strip_prefix('-') {
+        rest
+    } else {
+        let len = structured_data_len(rest);
+        message.structured_data = nil(&rest[..len]);
+        &rest[len..]
+    };
+
+    let body = rest.strip_prefix(' ').unwrap_or(rest);
+    message.message = body.trim_start_matches('\u{feff}').to_string();
+}
+
+/// RFC 3164 timestamps carry no year or zone; assume local time in the current year
+fn parse_bsd_timestamp(input: &str) -> Option<(DateTime<Utc>, &str)> {
+    if input.len() < 16 || input.as_bytes()[15] != b' ' {
+        return None;
+    }
+    let now = Local::now();
+    let stamp = format!("{} {}", now.year(), &input[..15]);
+    let naive = NaiveDateTime::parse_from_str(&stamp, "%Y %b %e %H:%M:%S").ok()?;
+    let mut local = Local.from_local_datetime(&naive).earliest()?;
+    // December messages received in January belong to last year
+    if local > now + chrono::Duration::days(1) {
+        let naive = naive.with_year(now.year() - 1)?;
+        local = Local.from_local_datetime(&naive).earliest()?;
+    }
+    Some((local.with_timezone(&Utc), &input[16..]))
+}
+
+fn parse_rfc3164(input: &str, message: &mut SyslogMessage) {
+    let rest = match parse_bsd_timestamp(input) {
+        Some((timestamp, rest)) => {
+            message.timestamp = Some(timestamp);
+            rest
+        }
+        None => input,
+    };
+
+    // Local daemons writing to /dev/log omit the hostname: "TAG[pid]: msg"
+    let (first, after_first) = next_field(rest);
+    let (tag_part, body) = if first.ends_with(':') || first.contains('[') {
+        (first, after_first)
+    } else if message.timestamp.is_some() {
+        message.hostname = nil(first);
+        next_field(after_first)
+    } else {
+        ("", rest)
+    };
+
+    let tag = tag_part.trim_end_matches(':');
+    if tag.is_empty() || !(tag_part.ends_with(':') || tag.ends_with(']')) {
+        // No TAG, everything after the header is content
+        message.message = match (tag_part, body) {
+            ("", body) | (body, "") => body.to_string(),
+            (tag_part, body) => format!("{} {}", tag_part, body),
+        };
+        return;
+    }
+
+    match tag.split_once('[') {
+        Some((app, pid)) => {
+            message.app_name = nil(app);
+            message.proc_id = nil(pid.trim_end_matches(']'));
+        }
+        None => message.app_name = nil(tag),
+    }
+    message.message = body.to_string();
+}
+
+fn parse_message(input: &str) -> SyslogMessage {
+    let input = input.trim_end_matches(['\n', '\r', '\0']);
+    let mut message = SyslogMessage::default();
+
+    let Some((facility, severity, rest)) = parse_priority(input) else {
+        message.message = input.to_string();
+        return message;
+    };
+    message.facility = Some(facility);
+    message.severity = Some(severity);
+
+    match rest.strip_prefix("1 ") {
+        Some(rest) => parse_rfc5424(rest, &mut message),
+        None => parse_rfc3164(rest, &mut message),
+    }
+    message
+}
+
+fn to_entry(message: SyslogMessage, transport: &str, peer: Option<String>, mapper: &SeverityMapper) -> LogEntry {
+    let severity = match message.severity {
+        Some(severity) => Severity::from_syslog(severity),
+        None => mapper.detect(&message.message).unwrap_or(Severity::INFO),
+    };
+
+    let mut attributes = serde_json::Map::new();
+    attributes.insert("source_type".to_string(), "syslog".into());
+    attributes.insert("syslog.transport".to_string(), transport.into());
+    let mut insert = |key: &str, value: Option<&String>| {
+        if let Some(value) = value {
+            attributes.insert(key.to_string(), value.as_str().into());
+        }
+    };
+    insert("syslog.facility", message.facility.map(|f| FACILITIES[f as usize].to_string()).as_ref());
+    insert("syslog.hostname", message.hostname.as_ref());
+    insert("syslog.app_name", message.app_name.as_ref());
+    insert("syslog.proc_id", message.proc_id.as_ref());
+    insert("syslog.msg_id", message.msg_id.as_ref());
+    insert("syslog.structured_data", message.structured_data.as_ref());
+    insert("syslog.peer", peer.as_ref());
+
+    LogEntry {
+        timestamp: message.timestamp.unwrap_or_else(Utc::now),
+        observed_timestamp: Utc::now(),
+        severity,
+        service: message
+            .app_name
+            .or(message.hostname)
+            .unwrap_or_else(|| "syslog".to_string()),
+        message: message.message,
+        body: None,
+        attributes: Some(serde_json::Value::Object(attributes)),
+        trace: None,
+        scope: None,
+        resource: None,
+    }
+}
+
+/// Read one TCP frame: octet-counted ("LEN SP MSG") or newline-terminated
+async fn read_frame<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Option<String>> {
+    let first = match reader.fill_buf().await?.first() {
+        Some(byte) => *byte,
+        None => return Ok(None),
+    };
+
+    if first.is_ascii_digit() {
+        let mut len = Vec::new();
+        reader.take(MAX_OCTET_COUNT_PREFIX).read_until(b' ', &mut len).await?;
+        if len.last() != Some(&b' ') {
+            anyhow::bail!("Octet count is not followed by a space within {} bytes", MAX_OCTET_COUNT_PREFIX);
+        }
+        let len: usize = std::str::from_utf8(&len)?
+            .trim_end()
+            .parse()
+            .context("Invalid octet count")?;
+        if len > MAX_MESSAGE_SIZE {
+            anyhow::bail!("Syslog frame too large: {} bytes", len);
+        }
+        let mut frame = vec![0u8; len];
+        reader.read_exact(&mut frame).await?;
+        return Ok(Some(String::from_utf8_lossy(&frame).into_owned()));
+    }
+
+    let mut frame = Vec::new();
+    let read = reader.take(MAX_MESSAGE_SIZE as u64).read_until(b'\n', &mut frame).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&frame).into_owned()))
+}
+
+async fn serve_udp(addr: String, mapper: Arc<SeverityMapper>, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    let socket = UdpSocket::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind syslog UDP socket {}", addr))?;
+    info!("Syslog listening on udp://{}", addr);
+
+    let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await?;
+        let message = parse_message(&String::from_utf8_lossy(&buf[..len]));
+        if tx.send(to_entry(message, "udp", Some(peer.to_string()), &mapper)).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+async fn serve_tcp(addr: String, mapper: Arc<SeverityMapper>, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind syslog TCP socket {}", addr))?;
+    info!("Syslog listening on tcp://{}", addr);
+
+    // Connections end with the listener, e.g. when the provider is reloaded
+    let mut connections = JoinSet::new();
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        while connections.try_join_next().is_some() {}
+        let mapper = mapper.clone();
+        let tx = tx.clone();
+
+        connections.spawn(async move {
+            let mut reader = BufReader::new(stream);
+            loop {
+                match read_frame(&mut reader).await {
+                    Ok(Some(frame)) => {
+                        if frame.trim().is_empty() {
+                            continue;
+                        }
+                        let entry = to_entry(parse_message(&frame), "tcp", Some(peer.to_string()), &mapper);
+                        if tx.send(entry).await.is_err() {
+                            return;
+                        }
+                    }
+                    Ok(None) => return,
+                    Err(e) => {
+                        warn!("Dropping syslog connection from {}: {}", peer, e);
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn serve_unix(path: &Path, mapper: Arc<SeverityMapper>, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+
+    // Remove a stale socket left behind by a previous run, but nothing else
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove stale socket {}", path.display()))?,
+        Ok(_) => anyhow::bail!("{} exists and is not a socket", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to inspect {}", path.display())),
+    }
+    let socket = UnixDatagram::bind(path)
+        .with_context(|| format!("Failed to bind syslog socket {}", path.display()))?;
+    info!("Syslog listening on unix://{}", path.display());
+
+    let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+    loop {
+        let len = socket.recv(&mut buf).await?;
+        let message = parse_message(&String::from_utf8_lossy(&buf[..len]));
+        if tx.send(to_entry(message, "unix", None, &mapper)).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LogProvider for SyslogProvider {
+    async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
+        let syslog_config = match &self.config.sources.syslog {
+            Some(cfg) if cfg.enabled => cfg.clone(),
+            _ => {
+                warn!("Syslog provider is not enabled");
+                return Ok(());
+            }
+        };
+
+        let mapper = Arc::new(SeverityMapper::from_config(&self.config.severity)?);
+        // Dropped with this future, so stopping the provider closes the listeners
+        let mut listeners = JoinSet::new();
+
+        if let Some(addr) = syslog_config.udp {
+            listeners.spawn(serve_udp(addr, mapper.clone(), tx.clone()));
+        }
+        if let Some(addr) = syslog_config.tcp {
+            listeners.spawn(serve_tcp(addr, mapper.clone(), tx.clone()));
+        }
+        if let Some(path) = syslog_config.unix {
+            let (mapper, tx) = (mapper.clone(), tx.clone());
+            listeners.spawn(async move { serve_unix(&path, mapper, tx).await });
+        }
+
+        if listeners.is_empty() {
+            warn!("Syslog provider has no udp, tcp or unix listener configured");
+        }
+
+        // A failed listener fails the provider, so the supervisor restarts it;
+        // a closed channel means the supervisor is stopping us
+        loop {
+            tokio::select! {
+                result = listeners.join_next() => match result {
+                    Some(result) => result??,
+                    None => break,
+                },
+                _ = tx.closed() => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "syslog"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc5424() {
+        let message = parse_message(
+            "<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 \
+             [exampleSDID@32473 iut=\"3\" eventSource=\"App]lication\"] \u{feff}An application event",
+        );
+        assert_eq!(message.facility, Some(20));
+        assert_eq!(message.severity, Some(5));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+        assert_eq!(message.app_name.as_deref(), Some("evntslog"));
+        assert_eq!(message.proc_id, None);
+        assert_eq!(message.msg_id.as_deref(), Some("ID47"));
+        assert_eq!(
+            message.structured_data.as_deref(),
+            Some("[exampleSDID@32473 iut=\"3\" eventSource=\"App]lication\"]")
+        );
+        assert_eq!(message.message, "An application event");
+        assert_eq!(message.timestamp.unwrap().timestamp_subsec_millis(), 3);
+    }
+
+    #[test]
+    fn test_parse_rfc5424_nil_values() {
+        let message = parse_message("<14>1 - - - - - -");
+        assert_eq!(message.severity, Some(6));
+        assert_eq!(message.timestamp, None);
+        assert_eq!(message.message, "");
+    }
+
+    #[test]
+    fn test_parse_rfc3164() {
+        let message = parse_message("<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed for lonvick on /dev/pts/8\n");
+        assert_eq!(message.facility, Some(4));
+        assert_eq!(message.severity, Some(2));
+        assert_eq!(message.hostname.as_deref(), Some("mymachine"));
+        assert_eq!(message.app_name.as_deref(), Some("su"));
+        assert_eq!(message.proc_id.as_deref(), Some("123"));
+        assert_eq!(message.message, "'su root' failed for lonvick on /dev/pts/8");
+        assert!(message.timestamp.is_some());
+
+        // /dev/log style, no hostname
+        let message = parse_message("<30>Jan  5 08:00:01 cron[99]: job started");
+        assert_eq!(message.hostname, None);
+        assert_eq!(message.app_name.as_deref(), Some("cron"));
+        assert_eq!(message.message, "job started");
+
+        let message = parse_message("<13>Feb 28 12:00:00 host plain text");
+        assert_eq!(message.hostname.as_deref(), Some("host"));
+        assert_eq!(message.app_name, None);
+        assert_eq!(message.message, "plain text");
+
+        let message = parse_message("no priority at all");
+        assert_eq!(message.severity, None);
+        assert_eq!(message.message, "no priority at all");
+    }
+
+    #[test]
+    fn test_to_entry() {
+        let mapper = SeverityMapper::from_config(&Default::default()).unwrap();
+        let entry = to_entry(parse_message("<11>1 - web01 nginx 42 - - upstream timed out"), "udp", None, &mapper);
+        assert_eq!(entry.service, "nginx");
+        assert_eq!(entry.severity, Severity::ERROR);
+        let attributes = entry.attributes.unwrap();
+        assert_eq!(attributes["syslog.facility"], "user");
+        assert_eq!(attributes["syslog.hostname"], "web01");
+        assert_eq!(attributes["syslog.proc_id"], "42");
+    }
+
+    #[tokio::test]
+    async fn test_read_frames() {
+        let data: &[u8] = b"11 <14>1 - - -18 <14>1 - - - - - hi<13>plain line\n";
+        let mut reader = BufReader::new(data);
+        assert_eq!(read_frame(&mut reader).await.unwrap().as_deref(), Some("<14>1 - - -"));
+        assert_eq!(read_frame(&mut reader).await.unwrap().as_deref(), Some("<14>1 - - - - - hi"));
+        assert_eq!(read_frame(&mut reader).await.unwrap().as_deref(), Some("<13>plain line\n"));
+        assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+
+        // An endless or oversized octet count is rejected without buffering it
+        let data = vec![b'1'; 1024 * 1024];
+        let mut reader = BufReader::new(&data[..]);
+        assert!(read_frame(&mut reader).await.is_err());
+        let mut reader = BufReader::new(&b"99999999 <14>hi"[..]);
+        assert!(read_frame(&mut reader).await.unwrap_err().to_string().contains("too large"));
+    }
+
+    #[tokio::test]
+    async fn test_listener_error_fails_start() {
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let toml = format!(
+            r#"
+            [agent]
+            server = "127.0.0.1:1"
+            token = "test"
+
+            [sources.syslog]
+            enabled = true
+            tcp = "{}"
+            "#,
+            taken.local_addr().unwrap()
+        );
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        let provider = SyslogProvider::new(Arc::new(config.try_deserialize().unwrap()));
+
+        let (tx, _rx) = mpsc::channel(1);
+        let error = provider.start(tx).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("Failed to bind syslog TCP socket"));
+    }
+
+    #[tokio::test]
+    async fn test_unix_keeps_non_socket_files() {
+        let path = std::env::temp_dir().join(format!("ilog-syslog-{}.log", std::process::id()));
+        std::fs::write(&path, "keep me").unwrap();
+
+        let mapper = Arc::new(SeverityMapper::from_config(&Default::default()).unwrap());
+        let (tx, _rx) = mpsc::channel(1);
+        let error = serve_unix(&path, mapper, tx).await.unwrap_err();
+        assert!(error.to_string().contains("is not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
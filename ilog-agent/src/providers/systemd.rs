@@ -1,112 +1,427 @@
 use tokio::sync::mpsc;
 use anyhow::{Result, Context};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
+use systemd::journal::{Journal, OpenOptions};
+use tokio::task::JoinSet;
 use tracing::{info, error, warn};
 
-use crate::config::AgentConfig;
+use crate::config::{AgentConfig, JournaldSource};
+use crate::service_name::ServiceNameTemplate;
+use crate::severity::Severity;
+use crate::state::StateStore;
 use crate::tcp_sender::LogEntry;
 use super::LogProvider;
 
+const CURSOR_KEY: &str = "cursor";
+
 pub struct SystemdProvider {
     config: Arc<AgentConfig>,
+    state: Arc<StateStore>,
 }
 
 impl SystemdProvider {
     pub fn new(config: Arc<AgentConfig>) -> Self {
-        Self { config }
+        let state = StateStore::open(&config.agent.state_dir, "journald");
+        Self { config, state }
+    }
+}
+
+/// Parse a syslog priority name or number ("err", "4")
+fn parse_priority(value: &str) -> Option<u8> {
+    let value = value.trim();
+    if let Ok(number) = value.parse::<u8>() {
+        return (number <= 7).then_some(number);
+    }
+
+    let names = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+    names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+        .map(|p| p as u8)
+}
+
+/// Priorities selected by a journalctl-style filter: a single level means
+/// "this and more severe", `FROM..TO` selects an inclusive range
+fn parse_priority_range(value: &str) -> Result<Vec<u8>> {
+    let invalid = || anyhow::anyhow!("sources.journald.priority: invalid priority '{}'", value);
+
+    let (from, to) = match value.split_once("..") {
+        Some((from, to)) => (
+            parse_priority(from).ok_or_else(invalid)?,
+            parse_priority(to).ok_or_else(invalid)?,
+        ),
+        None => (0, parse_priority(value).ok_or_else(invalid)?),
+    };
+
+    Ok((from.min(to)..=from.max(to)).collect())
+}
+
+/// Journal matches as OR'ed branches of AND'ed `FIELD=value` terms.
+/// Terms on the same field inside a branch are OR'ed by sd-journal itself.
+fn build_matches(config: &JournaldSource) -> Result<Vec<Vec<(String, String)>>> {
+    let mut common = Vec::new();
+
+    if let Some(priority) = &config.priority {
+        for p in parse_priority_range(priority)? {
+            common.push(("PRIORITY".to_string(), p.to_string()));
+        }
+    }
+
+    for term in &config.matches {
+        let (field, value) = term
+            .split_once('=')
+            .filter(|(field, _)| !field.is_empty())
+            .with_context(|| format!("sources.journald.matches: expected FIELD=value, got '{}'", term))?;
+        common.push((field.to_string(), value.to_string()));
+    }
+
+    // Units and identifiers are alternatives; every other match applies to both
+    let mut branches = Vec::new();
+    if !config.units.is_empty() {
+        let mut branch: Vec<(String, String)> = config
+            .units
+            .iter()
+            .map(|unit| {
+                let unit = if unit.contains('.') { unit.clone() } else { format!("{}.service", unit) };
+                ("_SYSTEMD_UNIT".to_string(), unit)
+            })
+            .collect();
+        branch.extend(common.iter().cloned());
+        branches.push(branch);
+    }
+    if !config.identifiers.is_empty() {
+        let mut branch: Vec<(String, String)> = config
+            .identifiers
+            .iter()
+            .map(|id| ("SYSLOG_IDENTIFIER".to_string(), id.clone()))
+            .collect();
+        branch.extend(common.iter().cloned());
+        branches.push(branch);
+    }
+    if branches.is_empty() && !common.is_empty() {
+        branches.push(common);
+    }
+
+    Ok(branches)
+}
+
+/// Attribute name for a forwarded journal field ("_PID" -> "pid")
+fn attribute_name(field: &str) -> String {
+    field.trim_start_matches('_').to_lowercase()
+}
+
+fn record_to_entry(
+    record: &BTreeMap<String, String>,
+    timestamp_usec: Option<u64>,
+    fields: &[String],
+    service_name: Option<&ServiceNameTemplate>,
+) -> LogEntry {
+    let unit = record
+        .get("_SYSTEMD_UNIT")
+        .or_else(|| record.get("UNIT"))
+        .map(String::as_str);
+    let rendered = service_name.and_then(|template| {
+        template.render(|placeholder| match placeholder {
+            "unit" => unit.map(|u| Cow::Borrowed(u.strip_suffix(".service").unwrap_or(u))),
+            _ => placeholder
+                .strip_prefix("field:")
+                .and_then(|field| record.get(field))
+                .map(|value| Cow::Borrowed(value.as_str())),
+        })
+    });
+    let service = rendered.unwrap_or_else(|| {
+        unit.or_else(|| record.get("SYSLOG_IDENTIFIER").map(String::as_str))
+            .unwrap_or("journald")
+            .to_string()
+    });
+
+    let severity = record
+        .get("PRIORITY")
+        .and_then(|p| p.parse::<u8>().ok())
+        .map(Severity::from_syslog)
+        .unwrap_or(Severity::INFO);
+
+    let timestamp = timestamp_usec
+        .and_then(|usec| chrono::DateTime::from_timestamp_micros(usec as i64))
+        .unwrap_or_else(chrono::Utc::now);
+
+    let mut attributes = serde_json::Map::new();
+    attributes.insert("source_type".to_string(), "journald".into());
+    if let Some(unit) = unit {
+        attributes.insert("unit".to_string(), unit.into());
+    }
+    for field in fields {
+        if let Some(value) = record.get(field) {
+            attributes.insert(attribute_name(field), value.as_str().into());
+        }
+    }
+
+    LogEntry {
+        timestamp,
+        observed_timestamp: chrono::Utc::now(),
+        severity,
+        service,
+        message: record.get("MESSAGE").cloned().unwrap_or_default(),
+        body: None,
+        attributes: Some(serde_json::Value::Object(attributes)),
+        trace: None,
+        scope: None,
+        resource: None,
     }
 }
 
-#[async_trait]
+fn open_journal(matches: &[Vec<(String, String)>], cursor: Option<&str>) -> Result<Journal> {
+    let mut journal = OpenOptions::default()
+        .open()
+        .context("Failed to open systemd journal")?;
+
+    for (i, branch) in matches.iter().enumerate() {
+        if i > 0 {
+            journal.match_or()?;
+        }
+        for (field, value) in branch {
+            journal.match_add(field, value.as_str())?;
+        }
+    }
+
+    match cursor {
+        Some(cursor) => {
+            journal.seek_cursor(cursor).context("Failed to seek to saved journal cursor")?;
+            // The cursor entry itself was already delivered
+            if journal.next()? > 0 && !journal.test_cursor(cursor)? {
+                journal.previous()?;
+            }
+        }
+        None => {
+            // Only new entries; previous() positions on the last existing entry
+            journal.seek_tail().context("Failed to seek to end of journal")?;
+            journal.previous()?;
+        }
+    }
+
+    Ok(journal)
+}
+
+/// Blocking read loop, runs on its own thread
+fn follow_journal(
+    config: JournaldSource,
+    service_name: Option<ServiceNameTemplate>,
+    matches: Vec<Vec<(String, String)>>,
+    state: Arc<StateStore>,
+    tx: mpsc::Sender<LogEntry>,
+) -> Result<()> {
+    let cursor = state.get(CURSOR_KEY);
+    let mut journal = match open_journal(&matches, cursor.as_deref()) {
+        Ok(journal) => journal,
+        Err(e) if cursor.is_some() => {
+            warn!("Saved journal cursor is unusable ({:#}), starting from the end", e);
+            open_journal(&matches, None)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    loop {
+        match journal.next_entry() {
+            Ok(Some(record)) => {
+                let entry = record_to_entry(&record, journal.timestamp_usec().ok(), &config.fields, service_name.as_ref());
+                if entry.message.is_empty() {
+                    continue;
+                }
+
+                if let Err(e) = tx.blocking_send(entry) {
+                    error!("Failed to send log entry: {}", e);
+                    return Ok(());
+                }
+
+                match journal.cursor() {
+                    Ok(cursor) => state.set(CURSOR_KEY, cursor),
+                    Err(e) => warn!("Failed to read journal cursor: {}", e),
+                }
+            }
+            Ok(None) => {
+                if tx.is_closed() {
+                    return Ok(());
+                }
+                if let Err(e) = journal.wait(Some(Duration::from_secs(1))) {
+                    error!("Error waiting for journal: {}", e);
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            }
+            Err(e) => {
+                error!("Error reading journal: {}", e);
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
 impl LogProvider for SystemdProvider {
     async fn start(&self, tx: mpsc::Sender<LogEntry>) -> Result<()> {
         let journald_config = match &self.config.sources.journald {
-            Some(cfg) if cfg.enabled => cfg,
+            Some(cfg) if cfg.enabled => cfg.clone(),
             _ => {
                 warn!("Systemd provider is not enabled");
                 return Ok(());
             }
         };
 
-        info!("Starting systemd journald provider for {} units", journald_config.units.len());
-
-        // Open the journal
-        let mut journal = Journal::open(systemd::journal::JournalFiles::All, false, true)
-            .context("Failed to open systemd journal")?;
-
-        // Seek to end (only new entries)
-        journal.seek_tail()
-            .context("Failed to seek to end of journal")?;
-
-        info!("Watching journald for units: {:?}", journald_config.units);
-
-        // Follow the journal
-        loop {
-            match journal.next_entry() {
-                Ok(Some(entry)) => {
-                    let unit = entry.get("_SYSTEMD_UNIT")
-                        .or_else(|| entry.get("UNIT"))
-                        .unwrap_or("unknown");
-
-                    if !journald_config.units.iter().any(|u| unit.contains(u)) {
-                        continue;
-                    }
-
-                    let message = entry.get("MESSAGE").unwrap_or("");
-                    let priority = entry.get("PRIORITY").unwrap_or("6"); // 6 = INFO
-                    
-                    let level = match priority {
-                        "0" | "1" | "2" | "3" => "ERROR",
-                        "4" => "WARN",
-                        "5" | "6" => "INFO",
-                        "7" => "DEBUG",
-                        _ => "INFO",
-                    };
-
-                    let timestamp = if let Some(ts_str) = entry.get("__REALTIME_TIMESTAMP") {
-                        if let Ok(ts_micros) = ts_str.parse::<i64>() {
-                            chrono::DateTime::from_timestamp_micros(ts_micros)
-                                .unwrap_or_else(|| chrono::Utc::now())
-                        } else {
-                            chrono::Utc::now()
-                        }
-                    } else {
-                        chrono::Utc::now()
-                    };
-
-                    let log_entry = LogEntry {
-                        timestamp,
-                        level: level.to_string(),
-                        service: unit.to_string(),
-                        message: message.to_string(),
-                        attributes: Some(serde_json::json!({
-                            "source_type": "journald",
-                            "unit": unit,
-                            "pid": entry.get("_PID").unwrap_or(""),
-                            "uid": entry.get("_UID").unwrap_or(""),
-                        })),
-                    };
-
-                    if let Err(e) = tx.send(log_entry).await {
-                        error!("Failed to send log entry: {}", e);
-                        break;
-                    }
-                }
-                Ok(None) => {
-                    // No new entries, wait a bit
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                }
-                Err(e) => {
-                    error!("Error reading journal: {}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                }
-            }
-        }
+        let matches = build_matches(&journald_config)?;
+        let service_name = journald_config
+            .service_name
+            .as_deref()
+            .map(ServiceNameTemplate::parse)
+            .transpose()
+            .context("sources.journald.service_name")?;
+        info!(
+            "Starting systemd journald provider (units: {:?}, identifiers: {:?})",
+            journald_config.units, journald_config.identifiers
+        );
 
+        // Dropped with this future, so stopping the provider stops the flusher;
+        // the reader thread ends once the channel closes
+        let mut flusher = JoinSet::new();
+        flusher.spawn(self.state.clone().flush_every(Duration::from_secs(1)));
+
+        let state = self.state.clone();
+        tokio::task::spawn_blocking(move || follow_journal(journald_config, service_name, matches, state, tx))
+            .await
+            .context("Journal reader thread panicked")??;
+
+        self.state.save()?;
         Ok(())
     }
-    
+
     fn name(&self) -> &str {
         "systemd"
     }
+
+    fn state(&self) -> Option<&StateStore> {
+        Some(&self.state)
+    }
+
+    async fn check(&self) -> Result<Vec<String>> {
+        let Some(journald_config) = &self.config.sources.journald else {
+            return Ok(Vec::new());
+        };
+        let matches = build_matches(journald_config)?;
+        if let Some(template) = &journald_config.service_name {
+            ServiceNameTemplate::parse(template).context("sources.journald.service_name")?;
+        }
+
+        // Units and identifiers without any entry are usually typos
+        let names: Vec<(String, String)> = matches
+            .into_iter()
+            .flatten()
+            .filter(|(field, _)| field == "_SYSTEMD_UNIT" || field == "SYSLOG_IDENTIFIER")
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            names
+                .into_iter()
+                .map(|(field, value)| {
+                    let found = has_entries(&field, &value)?;
+                    Ok(format!("{}={}: {}", field, value, if found { "found in journal" } else { "no journal entries" }))
+                })
+                .collect()
+        })
+        .await
+        .context("Journal check panicked")?
+    }
+}
+
+/// Whether the journal holds any entry with `field=value`
+fn has_entries(field: &str, value: &str) -> Result<bool> {
+    let mut journal = OpenOptions::default()
+        .open()
+        .context("Failed to open systemd journal")?;
+    journal.match_add(field, value)?;
+    journal.seek_head()?;
+    Ok(journal.next()? > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source() -> JournaldSource {
+        JournaldSource {
+            enabled: true,
+            units: vec![],
+            identifiers: vec![],
+            priority: None,
+            matches: vec![],
+            fields: vec!["_PID".to_string(), "_HOSTNAME".to_string()],
+            service_name: None,
+        }
+    }
+
+    #[test]
+    fn test_priority_range() {
+        assert_eq!(parse_priority_range("warning").unwrap(), vec![0, 1, 2, 3, 4]);
+        assert_eq!(parse_priority_range("err..crit").unwrap(), vec![2, 3]);
+        assert_eq!(parse_priority_range("6..7").unwrap(), vec![6, 7]);
+        assert!(parse_priority_range("loud").is_err());
+    }
+
+    #[test]
+    fn test_build_matches() {
+        let mut config = source();
+        assert!(build_matches(&config).unwrap().is_empty());
+
+        config.units = vec!["nginx".to_string(), "cron.timer".to_string()];
+        config.identifiers = vec!["sshd".to_string()];
+        config.priority = Some("err".to_string());
+        config.matches = vec!["_TRANSPORT=syslog".to_string()];
+
+        let matches = build_matches(&config).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(matches[0].contains(&("_SYSTEMD_UNIT".to_string(), "nginx.service".to_string())));
+        assert!(matches[0].contains(&("_SYSTEMD_UNIT".to_string(), "cron.timer".to_string())));
+        assert!(matches[1].contains(&("SYSLOG_IDENTIFIER".to_string(), "sshd".to_string())));
+        for branch in &matches {
+            assert!(branch.contains(&("PRIORITY".to_string(), "3".to_string())));
+            assert!(branch.contains(&("_TRANSPORT".to_string(), "syslog".to_string())));
+        }
+
+        config.matches = vec!["nonsense".to_string()];
+        assert!(build_matches(&config).is_err());
+    }
+
+    #[test]
+    fn test_record_to_entry() {
+        let mut record = BTreeMap::new();
+        record.insert("MESSAGE".to_string(), "Started nginx".to_string());
+        record.insert("PRIORITY".to_string(), "3".to_string());
+        record.insert("_SYSTEMD_UNIT".to_string(), "nginx.service".to_string());
+        record.insert("_PID".to_string(), "42".to_string());
+
+        let entry = record_to_entry(&record, Some(1_700_000_000_000_000), &source().fields, None);
+        assert_eq!(entry.service, "nginx.service");
+        assert_eq!(entry.severity, Severity::ERROR);
+        assert_eq!(entry.timestamp.timestamp(), 1_700_000_000);
+
+        let attributes = entry.attributes.unwrap();
+        assert_eq!(attributes["pid"], "42");
+        assert_eq!(attributes["unit"], "nginx.service");
+        assert!(attributes.get("hostname").is_none());
+    }
+
+    #[test]
+    fn test_record_service_name() {
+        let mut record = BTreeMap::new();
+        record.insert("MESSAGE".to_string(), "tick".to_string());
+        record.insert("_SYSTEMD_UNIT".to_string(), "billing-worker.service".to_string());
+        record.insert("SYSLOG_IDENTIFIER".to_string(), "worker".to_string());
+
+        let service = |template: &str| {
+            let template = ServiceNameTemplate::parse(template).unwrap();
+            record_to_entry(&record, None, &[], Some(&template)).service
+        };
+        assert_eq!(service("{unit}"), "billing-worker");
+        assert_eq!(service("{field:SYSLOG_IDENTIFIER}@{unit}"), "worker@billing-worker");
+        assert_eq!(service("{field:_COMM}"), "billing-worker.service");
+    }
 }
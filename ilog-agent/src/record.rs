@@ -0,0 +1,196 @@
+use chrono::{DateTime, Utc};
+use serde_json::{Map, Value};
+
+use crate::severity::{Severity, SeverityMapper};
+use crate::tcp_sender::LogEntry;
+
+const MESSAGE_FIELDS: &[&str] = &["message", "msg", "log", "body"];
+const SEVERITY_FIELDS: &[&str] = &["level", "severity", "severityText", "severityNumber", "lvl"];
+const TIMESTAMP_FIELDS: &[&str] = &["timestamp", "time", "ts", "@timestamp"];
+const SERVICE_FIELDS: &[&str] = &["service", "service.name", "serviceName", "app"];
+
+/// Turns records pushed by local applications (JSON objects or plain text lines) into entries
+pub struct RecordParser {
+    service: String,
+    source_type: &'static str,
+    severity: SeverityMapper,
+}
+
+fn take_first(record: &mut Map<String, Value>, fields: &[&str]) -> Option<Value> {
+    fields.iter().find_map(|field| record.remove(*field))
+}
+
+fn value_to_string(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// RFC 3339 strings, or Unix epoch numbers in seconds, milliseconds or nanoseconds
+fn parse_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|ts| ts.with_timezone(&Utc))
+            .ok()
+            .or_else(|| s.parse::<f64>().ok().and_then(|n| parse_timestamp(&Value::from(n)))),
+        Value::Number(n) => {
+            let n = n.as_f64()?;
+            if n >= 1e17 {
+                Some(DateTime::from_timestamp_nanos(n as i64))
+            } else if n >= 1e11 {
+                DateTime::from_timestamp_millis(n as i64)
+            } else {
+                DateTime::from_timestamp(n.trunc() as i64, (n.fract() * 1e9) as u32)
+            }
+        }
+        _ => None,
+    }
+}
+
+impl RecordParser {
+    pub fn new(service: impl Into<String>, source_type: &'static str, severity: SeverityMapper) -> Self {
+        Self {
+            service: service.into(),
+            source_type,
+            severity,
+        }
+    }
+
+    /// An NDJSON object or, failing that, the raw line as the message
+    pub fn parse_line(&self, line: &str) -> Option<LogEntry> {
+        let line = line.trim();
+        if line.is_empty() {
+            return None;
+        }
+
+        if line.starts_with('{') {
+            if let Ok(Value::Object(record)) = serde_json::from_str(line) {
+                return Some(self.object_entry(record));
+            }
+        }
+        Some(self.text_entry(line.to_string()))
+    }
+
+    /// A JSON record; non-object values are used as the message
+    #[cfg(feature = "http-server")]
+    pub fn parse_value(&self, value: Value) -> LogEntry {
+        match value {
+            Value::Object(record) => self.object_entry(record),
+            other => self.text_entry(value_to_string(other)),
+        }
+    }
+
+    fn text_entry(&self, message: String) -> LogEntry {
+        LogEntry {
+            timestamp: Utc::now(),
+            observed_timestamp: Utc::now(),
+            severity: self.severity.detect(&message).unwrap_or(Severity::INFO),
+            service: self.service.clone(),
+            message,
+            body: None,
+            attributes: Some(serde_json::json!({ "source_type": self.source_type })),
+            trace: None,
+            scope: None,
+            resource: None,
+        }
+    }
+
+    fn object_entry(&self, mut record: Map<String, Value>) -> LogEntry {
+        // A structured message field (`"msg": {...}`) is kept as the body
+        let (message, body) = match take_first(&mut record, MESSAGE_FIELDS) {
+            Some(value @ (Value::Object(_) | Value::Array(_))) => (value.to_string(), Some(value)),
+            Some(value) => (value_to_string(value), None),
+            None => (String::new(), None),
+        };
+        let severity = take_first(&mut record, SEVERITY_FIELDS)
+            .and_then(|value| Severity::parse(&value_to_string(value)))
+            .or_else(|| self.severity.detect(&message))
+            .unwrap_or(Severity::INFO);
+        let timestamp = take_first(&mut record, TIMESTAMP_FIELDS)
+            .and_then(|value| parse_timestamp(&value))
+            .unwrap_or_else(Utc::now);
+        let service = take_first(&mut record, SERVICE_FIELDS)
+            .map(value_to_string)
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| self.service.clone());
+
+        // Remaining fields become attributes, a nested "attributes" object is flattened in
+        let mut attributes = match record.remove("attributes") {
+            Some(Value::Object(nested)) => nested,
+            Some(other) => Map::from_iter([("attributes".to_string(), other)]),
+            None => Map::new(),
+        };
+        attributes.extend(record);
+        attributes.insert("source_type".to_string(), self.source_type.into());
+
+        LogEntry {
+            timestamp,
+            observed_timestamp: Utc::now(),
+            severity,
+            service,
+            message,
+            body,
+            attributes: Some(Value::Object(attributes)),
+            trace: None,
+            scope: None,
+            resource: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> RecordParser {
+        RecordParser::new("app", "socket", SeverityMapper::from_config(&Default::default()).unwrap())
+    }
+
+    #[test]
+    fn test_json_record() {
+        let entry = parser()
+            .parse_line(r#"{"msg":"user created","level":"warning","ts":1700000000.5,"service":"users","user_id":7,"attributes":{"tenant":"a"}}"#)
+            .unwrap();
+        assert_eq!(entry.message, "user created");
+        assert_eq!(entry.severity, Severity::WARN);
+        assert_eq!(entry.service, "users");
+        assert_eq!(entry.timestamp.timestamp_millis(), 1_700_000_000_500);
+
+        let attributes = entry.attributes.unwrap();
+        assert_eq!(attributes["user_id"], 7);
+        assert_eq!(attributes["tenant"], "a");
+        assert_eq!(attributes["source_type"], "socket");
+        assert!(attributes.get("msg").is_none());
+        assert_eq!(entry.body, None);
+
+        let entry = parser().parse_line(r#"{"log":{"event":"login","user":7}}"#).unwrap();
+        assert_eq!(entry.message, r#"{"event":"login","user":7}"#);
+        assert_eq!(entry.body.unwrap()["user"], 7);
+    }
+
+    #[test]
+    fn test_plain_line() {
+        let parser = parser();
+        let entry = parser.parse_line("[ERROR] disk full\n").unwrap();
+        assert_eq!(entry.message, "[ERROR] disk full");
+        assert_eq!(entry.severity, Severity::ERROR);
+        assert_eq!(entry.service, "app");
+
+        assert_eq!(parser.parse_line("{not json").unwrap().message, "{not json");
+        assert!(parser.parse_line("   ").is_none());
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let expected = 1_700_000_000;
+        for value in [
+            serde_json::json!("2023-11-14T22:13:20Z"),
+            serde_json::json!(1_700_000_000),
+            serde_json::json!(1_700_000_000_000i64),
+            serde_json::json!(1_700_000_000_000_000_000i64),
+        ] {
+            assert_eq!(parse_timestamp(&value).unwrap().timestamp(), expected);
+        }
+    }
+}
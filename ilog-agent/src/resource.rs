@@ -0,0 +1,90 @@
+use serde_json::{Map, Value};
+use std::path::Path;
+
+use crate::config::ResourceConfig;
+
+/// First non-empty line of a file, e.g. /etc/hostname or /etc/machine-id
+fn read_first_line(path: impl AsRef<Path>) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    let line = content.lines().next()?.trim();
+    (!line.is_empty()).then(|| line.to_string())
+}
+
+fn detect_hostname() -> Option<String> {
+    read_first_line("/proc/sys/kernel/hostname")
+        .or_else(|| read_first_line("/etc/hostname"))
+        .or_else(|| std::env::var("HOSTNAME").ok().filter(|h| !h.is_empty()))
+        .or_else(|| std::env::var("COMPUTERNAME").ok().filter(|h| !h.is_empty()))
+}
+
+fn detect_host_id() -> Option<String> {
+    read_first_line("/etc/machine-id").or_else(|| read_first_line("/var/lib/dbus/machine-id"))
+}
+
+/// OTel `os.type` values
+fn os_type() -> &'static str {
+    match std::env::consts::OS {
+        "macos" => "darwin",
+        other => other,
+    }
+}
+
+/// OTel `host.arch` values
+fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        "arm" => "arm32",
+        "powerpc64" => "ppc64",
+        "s390x" => "s390x",
+        other => other,
+    }
+}
+
+/// Attributes describing the host and the agent, sent once per batch.
+/// Static labels from the config are applied last and win over detected values.
+pub fn detect(config: &ResourceConfig) -> Map<String, Value> {
+    let mut attributes = Map::new();
+
+    if let Some(hostname) = config.hostname.clone().or_else(detect_hostname) {
+        attributes.insert("host.name".to_string(), hostname.into());
+    }
+    if let Some(host_id) = detect_host_id() {
+        attributes.insert("host.id".to_string(), host_id.into());
+    }
+    attributes.insert("host.arch".to_string(), host_arch().into());
+    attributes.insert("os.type".to_string(), os_type().into());
+    attributes.insert("telemetry.distro.name".to_string(), "ilog-agent".into());
+    attributes.insert("telemetry.distro.version".to_string(), env!("CARGO_PKG_VERSION").into());
+
+    for (key, value) in &config.attributes {
+        attributes.insert(key.clone(), value.clone().into());
+    }
+
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_detect_with_overrides() {
+        let config = ResourceConfig {
+            hostname: Some("web-1".to_string()),
+            attributes: HashMap::from([
+                ("deployment.environment".to_string(), "production".to_string()),
+                ("os.type".to_string(), "custom".to_string()),
+            ]),
+        };
+
+        let attributes = detect(&config);
+        assert_eq!(attributes["host.name"], "web-1");
+        assert_eq!(attributes["deployment.environment"], "production");
+        assert_eq!(attributes["os.type"], "custom");
+        assert_eq!(attributes["telemetry.distro.version"], env!("CARGO_PKG_VERSION"));
+        assert!(attributes.contains_key("host.arch"));
+    }
+}
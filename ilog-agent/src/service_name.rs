@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder(String),
+}
+
+/// `service_name` template such as `"billing"`, `"{1}"` or `"{label:com.example.service}"`.
+/// Placeholders are resolved by the provider; the provider's default naming is
+/// used when one of them has no value.
+#[derive(Debug, Clone)]
+pub struct ServiceNameTemplate {
+    parts: Vec<Part>,
+}
+
+impl ServiceNameTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let len = rest[start..]
+                .find('}')
+                .with_context(|| format!("unclosed '{{' in service name template '{}'", template))?;
+            let name = rest[start + 1..start + len].trim();
+            if name.is_empty() {
+                anyhow::bail!("empty placeholder in service name template '{}'", template);
+            }
+            parts.push(Part::Placeholder(name.to_string()));
+            rest = &rest[start + len + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// Render with `lookup` resolving placeholders; None if any is missing or the result is empty
+    pub fn render<'a>(&self, lookup: impl Fn(&str) -> Option<Cow<'a, str>>) -> Option<String> {
+        let mut service = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => service.push_str(text),
+                Part::Placeholder(name) => service.push_str(&lookup(name)?),
+            }
+        }
+        let service = service.trim();
+        (!service.is_empty()).then(|| service.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<Cow<'static, str>> {
+        match name {
+            "1" => Some(Cow::Borrowed("shop")),
+            "label:team" => Some(Cow::Borrowed("payments")),
+            "empty" => Some(Cow::Borrowed("")),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let render = |template: &str| ServiceNameTemplate::parse(template).unwrap().render(lookup);
+        assert_eq!(render("billing").as_deref(), Some("billing"));
+        assert_eq!(render("{1}-web").as_deref(), Some("shop-web"));
+        assert_eq!(render("{ label:team }/{1}").as_deref(), Some("payments/shop"));
+        assert_eq!(render("{missing}-web"), None);
+        assert_eq!(render("{empty}"), None);
+
+        assert!(ServiceNameTemplate::parse("{1").is_err());
+        assert!(ServiceNameTemplate::parse("a{}b").is_err());
+    }
+}
@@ -0,0 +1,226 @@
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+use anyhow::{Context, Result};
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+use std::collections::HashMap;
+
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+use crate::config::SeverityConfig;
+
+/// OpenTelemetry `SeverityNumber` (1-24)
+///
+/// Ranges: TRACE 1-4, DEBUG 5-8, INFO 9-12, WARN 13-16, ERROR 17-20, FATAL 21-24.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Severity(i32);
+
+impl Severity {
+    #[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server", feature = "otlp"))]
+    pub const TRACE: Severity = Severity(1);
+    pub const DEBUG: Severity = Severity(5);
+    pub const INFO: Severity = Severity(9);
+    pub const NOTICE: Severity = Severity(10);
+    pub const WARN: Severity = Severity(13);
+    pub const ERROR: Severity = Severity(17);
+    pub const CRITICAL: Severity = Severity(21);
+    pub const ALERT: Severity = Severity(23);
+    pub const EMERGENCY: Severity = Severity(24);
+
+    /// Build from a raw severity number, rejecting values outside 1-24
+    #[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server", feature = "otlp"))]
+    pub fn from_number(number: i32) -> Option<Self> {
+        (1..=24).contains(&number).then_some(Severity(number))
+    }
+
+    pub fn number(self) -> i32 {
+        self.0
+    }
+
+    /// Short severity name sent as `severityText`
+    pub fn text(self) -> &'static str {
+        match self.0 {
+            1..=4 => "TRACE",
+            5..=8 => "DEBUG",
+            9..=12 => "INFO",
+            13..=16 => "WARN",
+            17..=20 => "ERROR",
+            _ => "FATAL",
+        }
+    }
+
+    /// Map a syslog/journald priority (0 = emerg ... 7 = debug)
+    #[cfg(any(feature = "journald", feature = "syslog"))]
+    pub fn from_syslog(priority: u8) -> Self {
+        match priority {
+            0 => Self::EMERGENCY,
+            1 => Self::ALERT,
+            2 => Self::CRITICAL,
+            3 => Self::ERROR,
+            4 => Self::WARN,
+            5 => Self::NOTICE,
+            6 => Self::INFO,
+            _ => Self::DEBUG,
+        }
+    }
+
+    /// Parse a severity name ("warning", "ERR", "fatal") or an OTel severity number
+    #[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server", feature = "otlp"))]
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if let Ok(number) = text.parse::<i32>() {
+            return Self::from_number(number);
+        }
+
+        DEFAULT_KEYWORDS
+            .iter()
+            .find(|(keyword, _)| keyword.eq_ignore_ascii_case(text))
+            .map(|(_, severity)| *severity)
+    }
+}
+
+impl Default for Severity {
+    fn default() -> Self {
+        Self::INFO
+    }
+}
+
+/// Keywords recognised in log lines when no `[severity.keywords]` override is set
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server", feature = "otlp"))]
+const DEFAULT_KEYWORDS: &[(&str, Severity)] = &[
+    ("TRACE", Severity::TRACE),
+    ("DEBUG", Severity::DEBUG),
+    ("DBG", Severity::DEBUG),
+    ("INFO", Severity::INFO),
+    ("INFORMATION", Severity::INFO),
+    ("NOTICE", Severity::NOTICE),
+    ("WARN", Severity::WARN),
+    ("WARNING", Severity::WARN),
+    ("ERROR", Severity::ERROR),
+    ("ERR", Severity::ERROR),
+    ("CRIT", Severity::CRITICAL),
+    ("CRITICAL", Severity::CRITICAL),
+    ("FATAL", Severity::CRITICAL),
+    ("PANIC", Severity::CRITICAL),
+    ("ALERT", Severity::ALERT),
+    ("EMERG", Severity::EMERGENCY),
+    ("EMERGENCY", Severity::EMERGENCY),
+];
+
+/// Detects severity keywords in free-form log lines
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+pub struct SeverityMapper {
+    keywords: HashMap<String, Severity>,
+    pattern: Regex,
+}
+
+#[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+impl SeverityMapper {
+    pub fn from_config(config: &SeverityConfig) -> Result<Self> {
+        let mut keywords: HashMap<String, Severity> = DEFAULT_KEYWORDS
+            .iter()
+            .map(|(keyword, severity)| (keyword.to_string(), *severity))
+            .collect();
+
+        for (keyword, value) in &config.keywords {
+            let severity = Severity::parse(value).with_context(|| {
+                format!("severity.keywords.{}: unknown severity '{}'", keyword, value)
+            })?;
+            keywords.insert(keyword.to_uppercase(), severity);
+        }
+
+        // Longest first so "WARNING" wins over "WARN"
+        let mut alternatives: Vec<&String> = keywords.keys().collect();
+        alternatives.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+        let alternatives: Vec<String> = alternatives.iter().map(|k| regex::escape(k)).collect();
+
+        let pattern = Regex::new(&format!(
+            r"(?i)[\[(<]?\b({})\b[\])>]?:?",
+            alternatives.join("|")
+        ))
+        .context("Failed to build severity keyword pattern")?;
+
+        Ok(Self { keywords, pattern })
+    }
+
+    /// Severity of the first keyword found in the message
+    #[cfg(any(feature = "file", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+    pub fn detect(&self, message: &str) -> Option<Severity> {
+        let captures = self.pattern.captures(message)?;
+        self.keywords.get(&captures[1].to_uppercase()).copied()
+    }
+
+    /// Like `detect`, but also strips the matched keyword (and its brackets) from the message
+    #[cfg(feature = "docker")]
+    pub fn detect_and_clean(&self, message: &str) -> (Option<Severity>, String) {
+        let Some(captures) = self.pattern.captures(message) else {
+            return (None, message.to_string());
+        };
+
+        let severity = self.keywords.get(&captures[1].to_uppercase()).copied();
+        let matched = captures.get(0).unwrap();
+
+        let mut cleaned = String::with_capacity(message.len());
+        cleaned.push_str(message[..matched.start()].trim_end());
+        let rest = message[matched.end()..].trim_start();
+        if !cleaned.is_empty() && !rest.is_empty() {
+            cleaned.push(' ');
+        }
+        cleaned.push_str(rest);
+
+        (severity, cleaned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(any(feature = "journald", feature = "syslog"))]
+    #[test]
+    fn test_syslog_priorities() {
+        assert_eq!(Severity::from_syslog(3).number(), 17);
+        assert_eq!(Severity::from_syslog(4).text(), "WARN");
+        assert_eq!(Severity::from_syslog(6).number(), 9);
+        assert_eq!(Severity::from_syslog(0).text(), "FATAL");
+    }
+
+    #[cfg(any(feature = "file", feature = "docker", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server", feature = "otlp"))]
+    #[test]
+    fn test_parse() {
+        assert_eq!(Severity::parse("warning"), Some(Severity::WARN));
+        assert_eq!(Severity::parse("Err"), Some(Severity::ERROR));
+        assert_eq!(Severity::parse("18"), Some(Severity(18)));
+        assert_eq!(Severity::parse("0"), None);
+        assert_eq!(Severity::parse("verbose"), None);
+    }
+
+    #[cfg(feature = "docker")]
+    #[test]
+    fn test_detect_and_clean() {
+        let mapper = SeverityMapper::from_config(&SeverityConfig::default()).unwrap();
+        let (severity, message) = mapper.detect_and_clean("[ERROR] connection refused");
+        assert_eq!(severity, Some(Severity::ERROR));
+        assert_eq!(message, "connection refused");
+
+        let (severity, message) = mapper.detect_and_clean("db WARNING: slow query");
+        assert_eq!(severity, Some(Severity::WARN));
+        assert_eq!(message, "db slow query");
+    }
+
+    #[cfg(any(feature = "file", feature = "exec", feature = "syslog", feature = "kubernetes", feature = "socket", feature = "http-server"))]
+    #[test]
+    fn test_custom_keywords() {
+        let mut config = SeverityConfig::default();
+        let mapper = SeverityMapper::from_config(&config).unwrap();
+        assert_eq!(mapper.detect("0 errors reported"), None);
+
+        config.keywords.insert("failed".to_string(), "error".to_string());
+        let mapper = SeverityMapper::from_config(&config).unwrap();
+        assert_eq!(mapper.detect("job FAILED after 3 tries"), Some(Severity::ERROR));
+
+        config.keywords.insert("oops".to_string(), "bogus".to_string());
+        assert!(SeverityMapper::from_config(&config).is_err());
+    }
+}
@@ -1,7 +0,0 @@
-pub mod file;
-
-#[cfg(feature = "journald")]
-pub mod journald;
-
-#[cfg(feature = "docker")]
-pub mod docker;
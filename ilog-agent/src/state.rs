@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+/// Inode and length of a file, to tell whether a saved offset still belongs to it
+#[cfg(any(feature = "file", feature = "kubernetes"))]
+pub fn file_identity(path: &Path) -> Option<(u64, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    #[cfg(unix)]
+    let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+    #[cfg(not(unix))]
+    let inode = 0;
+    Some((inode, metadata.len()))
+}
+
+/// Offset saved for `key` as "<inode>:<offset>", if it was saved for `inode`
+#[cfg(any(feature = "file", feature = "kubernetes"))]
+pub fn saved_offset(state: &StateStore, key: &str, inode: u64) -> Option<u64> {
+    let value = state.get(key)?;
+    let (saved_inode, offset) = value.split_once(':')?;
+    (saved_inode.parse::<u64>().ok()? == inode).then(|| offset.parse().ok())?
+}
+
+/// Resume positions (timestamps, cursors, offsets) persisted across agent restarts
+///
+/// Each provider keeps its own `<state_dir>/<name>.json` file of string keys and values.
+pub struct StateStore {
+    path: PathBuf,
+    values: Mutex<HashMap<String, String>>,
+    dirty: AtomicBool,
+}
+
+impl StateStore {
+    /// Load the state file, starting empty if it is missing or unreadable
+    pub fn open(state_dir: &Path, name: &str) -> Arc<Self> {
+        let path = state_dir.join(format!("{}.json", name));
+
+        let values = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt state file {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                warn!("Failed to read state file {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+
+        Arc::new(Self {
+            path,
+            values: Mutex::new(values),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        let mut values = self.values.lock().unwrap();
+        if values.get(key) != Some(&value) {
+            values.insert(key.to_string(), value);
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    #[cfg(any(feature = "docker", feature = "kubernetes"))]
+    pub fn remove(&self, key: &str) {
+        if self.values.lock().unwrap().remove(key).is_some() {
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// Write the file if anything changed (atomically, via a temporary file)
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+
+        let data = serde_json::to_vec_pretty(&*self.values.lock().unwrap())?;
+        let result = (|| {
+            if let Some(dir) = self.path.parent() {
+                std::fs::create_dir_all(dir)
+                    .with_context(|| format!("Failed to create state directory {}", dir.display()))?;
+            }
+            let tmp = self.path.with_extension("json.tmp");
+            std::fs::write(&tmp, &data)
+                .with_context(|| format!("Failed to write {}", tmp.display()))?;
+            std::fs::rename(&tmp, &self.path)
+                .with_context(|| format!("Failed to replace {}", self.path.display()))
+        })();
+
+        if result.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        result
+    }
+
+    /// Save every `interval`; meant to run among the provider's own tasks,
+    /// the supervisor saves one last time once the provider has stopped
+    pub async fn flush_every(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.save() {
+                warn!("Failed to save state: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("ilog-state-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = StateStore::open(&dir, "docker");
+        assert_eq!(store.get("abc"), None);
+        store.set("abc", "2025-01-01T00:00:00.000000001Z");
+        #[cfg(any(feature = "docker", feature = "kubernetes"))]
+        {
+            store.set("gone", "1");
+            store.remove("gone");
+        }
+        store.save().unwrap();
+        drop(store);
+
+        let reopened = StateStore::open(&dir, "docker");
+        assert_eq!(reopened.get("abc").as_deref(), Some("2025-01-01T00:00:00.000000001Z"));
+        assert_eq!(reopened.get("gone"), None);
+
+        // Changes only reach the file on save
+        reopened.set("abc", "2025-01-02T00:00:00Z");
+        assert_eq!(StateStore::open(&dir, "docker").get("abc").as_deref(), Some("2025-01-01T00:00:00.000000001Z"));
+        reopened.save().unwrap();
+        assert_eq!(StateStore::open(&dir, "docker").get("abc").as_deref(), Some("2025-01-02T00:00:00Z"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
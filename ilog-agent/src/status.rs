@@ -0,0 +1,105 @@
+use anyhow::{Context, Result};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::health::Health;
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request headers read before giving up on a client
+const MAX_HEADER_LINES: usize = 100;
+
+/// Serve the health report as JSON on `GET /status` at `addr`
+pub async fn serve(addr: String, health: Arc<Health>) -> Result<()> {
+    let listener = TcpListener::bind(&addr)
+        .await
+        .with_context(|| format!("Failed to bind status endpoint to {}", addr))?;
+    info!("Status endpoint listening on http://{}/status", addr);
+    accept(listener, health).await
+}
+
+async fn accept(listener: TcpListener, health: Arc<Health>) -> Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await.context("Status endpoint accept failed")?;
+        let health = health.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(REQUEST_TIMEOUT, respond(stream, &health)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => debug!("Status request from {} failed: {:#}", peer, e),
+                Err(_) => debug!("Status request from {} timed out", peer),
+            }
+        });
+    }
+}
+
+/// Minimal HTTP/1.1: one request per connection, closed after the response
+async fn respond(mut stream: TcpStream, health: &Health) -> Result<()> {
+    let mut reader = BufReader::new(&mut stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+
+    // Read the headers so closing the socket does not reset the connection
+    let mut header = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        header.clear();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/status")) => ("200 OK", health.snapshot().to_string()),
+        _ => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::State;
+    use tokio::io::AsyncReadExt;
+
+    async fn get(addr: std::net::SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_status_endpoint() {
+        let health = Arc::new(Health::default());
+        health.set("file", State::Running);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(accept(listener, health));
+
+        let response = get(addr, "/status").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        let body: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["components"]["file"]["state"], "running");
+
+        assert!(get(addr, "/other").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
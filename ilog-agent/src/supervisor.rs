@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::buffer::Buffer;
+use crate::config::AgentConfig;
+use crate::health::{self, Health};
+use crate::providers::{self, LogProvider, Source};
+use crate::tcp_sender::LogEntry;
+
+/// Entries a provider can queue before it waits for the processor chain
+const PROVIDER_BUFFER: usize = 100;
+
+/// How often the config file is checked for changes
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long a stopped provider gets to wind down before it is aborted
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Running {
+    provider: Arc<dyn LogProvider>,
+    task: JoinHandle<()>,
+    stop: oneshot::Sender<()>,
+    forwarder: JoinHandle<()>,
+}
+
+/// Pass entries from one provider's channel to the shared buffer. On stop
+/// the channel is closed, which is the provider's signal to stop: its sends
+/// fail from then on and `Sender::closed` wakes it up while it is idle.
+/// Whatever was already queued is still delivered.
+async fn forward(
+    source: &'static str,
+    mut rx: mpsc::Receiver<LogEntry>,
+    buffer: Arc<Buffer>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    loop {
+        tokio::select! {
+            entry = rx.recv() => match entry {
+                Some(entry) => {
+                    if !buffer.push(source, entry).await {
+                        return;
+                    }
+                }
+                None => return,
+            },
+            _ = &mut stop => break,
+        }
+    }
+
+    rx.close();
+    while let Some(entry) = rx.recv().await {
+        if !buffer.push(source, entry).await {
+            return;
+        }
+    }
+}
+
+/// Starts the enabled providers, restarts them when they fail and applies
+/// config reloads to them
+pub struct Supervisor {
+    config: Arc<AgentConfig>,
+    buffer: Arc<Buffer>,
+    health: Arc<Health>,
+    running: HashMap<&'static str, Running>,
+}
+
+impl Supervisor {
+    pub fn new(config: Arc<AgentConfig>, buffer: Arc<Buffer>, health: Arc<Health>) -> Self {
+        Self {
+            config,
+            buffer,
+            health,
+            running: HashMap::new(),
+        }
+    }
+
+    pub fn start_all(&mut self) {
+        for source in providers::sources() {
+            if source.enabled(&self.config) {
+                self.start(&source);
+            }
+        }
+        info!("Started {} providers", self.running.len());
+    }
+
+    fn start(&mut self, source: &Source) {
+        let provider: Arc<dyn LogProvider> = source.build(self.config.clone(), self.health.clone()).into();
+        let (provider_tx, provider_rx) = mpsc::channel(PROVIDER_BUFFER);
+        let (stop_tx, stop_rx) = oneshot::channel();
+        let name = source.name;
+        let health = self.health.clone();
+
+        info!("Starting {} provider", provider.name());
+        let task = {
+            let provider = provider.clone();
+            tokio::spawn(async move {
+                health::supervise(&health, name, &provider_tx, || provider.start(provider_tx.clone())).await;
+            })
+        };
+
+        self.running.insert(name, Running {
+            provider,
+            task,
+            stop: stop_tx,
+            forwarder: tokio::spawn(forward(name, provider_rx, self.buffer.clone(), stop_rx)),
+        });
+    }
+
+    async fn stop(&mut self, name: &str) {
+        if let Some(running) = self.running.remove(name) {
+            info!("Stopping {} provider", running.provider.name());
+            let _ = running.stop.send(());
+            let _ = running.forwarder.await;
+
+            // Everything the provider sent is forwarded now, and so is
+            // everything its checkpoints cover
+            let mut task = running.task;
+            if tokio::time::timeout(STOP_TIMEOUT, &mut task).await.is_err() {
+                warn!("{} provider did not stop within {:?}, aborting it", name, STOP_TIMEOUT);
+                task.abort();
+                let _ = task.await;
+            }
+            #[cfg(any(feature = "file", feature = "docker", feature = "journald", feature = "kubernetes"))]
+            if let Some(state) = running.provider.state() {
+                if let Err(e) = state.save() {
+                    warn!("Failed to save {} provider state: {:#}", name, e);
+                }
+            }
+            self.health.remove(name);
+        }
+    }
+
+    /// Stop every provider and deliver what they queued, then close the
+    /// buffer so the processor chain and the log sender see their channels
+    /// close once it is empty
+    pub async fn shutdown(mut self) {
+        let names: Vec<&'static str> = self.running.keys().copied().collect();
+        for name in names {
+            self.stop(name).await;
+        }
+        self.buffer.close().await;
+    }
+
+    /// Switch to `new`, restarting only the providers whose settings changed
+    pub async fn reload(&mut self, new: Arc<AgentConfig>) {
+        let old = std::mem::replace(&mut self.config, new.clone());
+
+        if old.agent != new.agent
+            || old.processors != new.processors
+            || old.resource != new.resource
+            || old.buffer != new.buffer
+            || old.trace_context != new.trace_context
+            || old.outputs != new.outputs
+            || old.routes != new.routes
+        {
+            warn!("Changes to [agent], [processors], [resource], [buffer], [trace_context], [outputs] and [routes] take effect after a restart");
+        }
+
+        let mut changes = 0;
+        for source in providers::sources() {
+            let running = self.running.contains_key(source.name);
+            match (running, source.enabled(&new)) {
+                (true, false) => self.stop(source.name).await,
+                (true, true) if source.changed(&old, &new) => {
+                    self.stop(source.name).await;
+                    self.start(&source);
+                }
+                (false, true) => self.start(&source),
+                _ => continue,
+            }
+            changes += 1;
+        }
+
+        if changes == 0 {
+            info!("Configuration reloaded, no provider changes");
+        } else {
+            info!("Configuration reloaded, {} providers started, stopped or restarted", changes);
+        }
+    }
+}
+
+/// Modification time and size, to notice the config file being rewritten
+fn file_version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Versions of the config file and its fragments; adding or removing a
+/// fragment counts as a change too
+fn config_version(path: &Path) -> Vec<(PathBuf, Option<(SystemTime, u64)>)> {
+    AgentConfig::files(path)
+        .unwrap_or_else(|_| vec![path.to_path_buf()])
+        .into_iter()
+        .map(|file| {
+            let version = file_version(&file);
+            (file, version)
+        })
+        .collect()
+}
+
+/// Fires on SIGHUP and whenever the config file or one of its fragments changes
+pub fn reload_triggers(path: PathBuf) -> mpsc::Receiver<()> {
+    let (tx, rx) = mpsc::channel(1);
+
+    #[cfg(unix)]
+    {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            use tokio::signal::unix::{signal, SignalKind};
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    warn!("Cannot listen for SIGHUP: {}", e);
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                info!("Received SIGHUP, reloading configuration");
+                // A reload is already pending if the channel is full
+                let _ = tx.try_send(());
+            }
+        });
+    }
+
+    tokio::spawn(async move {
+        let mut version = config_version(&path);
+        let mut ticker = tokio::time::interval(CONFIG_POLL_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let current = config_version(&path);
+            if current != version {
+                version = current;
+                info!("Configuration file {} changed, reloading", path.display());
+                let _ = tx.try_send(());
+            }
+            if tx.is_closed() {
+                return;
+            }
+        }
+    });
+
+    rx
+}
+
+/// Resolves on Ctrl-C or SIGTERM (what systemd sends on stop)
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = terminate.recv() => info!("Received SIGTERM"),
+                _ = tokio::signal::ctrl_c() => info!("Received Ctrl-C"),
+            },
+            Err(e) => {
+                warn!("Cannot listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                info!("Received Ctrl-C");
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        info!("Received Ctrl-C");
+    }
+}
+
+#[cfg(all(test, feature = "exec", unix))]
+mod tests {
+    use super::*;
+
+    fn config(command: &str) -> Arc<AgentConfig> {
+        let toml = format!(
+            r#"
+            [agent]
+            server = "127.0.0.1:1"
+            token = "test"
+
+            [sources.exec]
+            enabled = true
+            commands = [{{ name = "ticker", command = "sh", args = ["-c", "{}"] }}]
+            "#,
+            command
+        );
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .unwrap();
+        Arc::new(config.try_deserialize().unwrap())
+    }
+
+    async fn next_message(rx: &mut mpsc::Receiver<LogEntry>) -> String {
+        loop {
+            let entry = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+            if entry.attributes.as_ref().unwrap().get("exec.event").is_none() {
+                return entry.message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_restarts_changed_provider() {
+        let initial = config("while true; do echo one; sleep 0.05; done");
+        let (buffer, mut rx) = Buffer::start(&initial, Arc::default()).unwrap();
+        let mut supervisor = Supervisor::new(initial, buffer, Arc::default());
+        supervisor.start_all();
+        assert_eq!(next_message(&mut rx).await, "one");
+
+        // Unchanged config keeps the running provider
+        let before = supervisor.running["exec"].task.id();
+        supervisor.reload(config("while true; do echo one; sleep 0.05; done")).await;
+        assert_eq!(supervisor.running["exec"].task.id(), before);
+
+        supervisor.reload(config("while true; do echo two; sleep 0.05; done")).await;
+        assert_ne!(supervisor.running["exec"].task.id(), before);
+        let mut message = next_message(&mut rx).await;
+        while message == "one" {
+            message = next_message(&mut rx).await;
+        }
+        assert_eq!(message, "two");
+
+        let mut disabled = (*config("true")).clone();
+        disabled.sources.exec.as_mut().unwrap().enabled = false;
+        supervisor.reload(Arc::new(disabled)).await;
+        assert!(supervisor.running.is_empty());
+    }
+
+    #[cfg(feature = "syslog")]
+    #[tokio::test]
+    async fn test_reload_rebinds_listener() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpStream;
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let syslog = |udp: &str| {
+            let mut config = (*config("true")).clone();
+            config.sources.exec.as_mut().unwrap().enabled = false;
+            config.sources.syslog = Some(crate::config::SyslogSource {
+                enabled: true,
+                udp: Some(udp.to_string()),
+                tcp: Some(addr.to_string()),
+                unix: None,
+            });
+            Arc::new(config)
+        };
+        async fn connect(addr: std::net::SocketAddr) -> TcpStream {
+            for _ in 0..100 {
+                if let Ok(stream) = TcpStream::connect(addr).await {
+                    return stream;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            panic!("syslog listener did not come up on {}", addr);
+        }
+
+        let initial = syslog("127.0.0.1:0");
+        let (buffer, mut rx) = Buffer::start(&initial, Arc::default()).unwrap();
+        let mut supervisor = Supervisor::new(initial, buffer, Arc::default());
+        supervisor.start_all();
+        let mut old = connect(addr).await;
+        old.write_all(b"<14>first\n").await.unwrap();
+        assert_eq!(next_message(&mut rx).await, "first");
+
+        // The restarted provider binds the same address, and connections
+        // accepted by the old listener are closed
+        supervisor.reload(syslog("127.0.0.2:0")).await;
+        let mut buf = [0; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), old.read(&mut buf)).await.unwrap();
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        let mut new = connect(addr).await;
+        new.write_all(b"<14>second\n").await.unwrap();
+        assert_eq!(next_message(&mut rx).await, "second");
+        supervisor.shutdown().await;
+    }
+
+    #[cfg(feature = "file")]
+    #[tokio::test]
+    async fn test_file_offsets_survive_restart() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("ilog-supervisor-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        std::fs::write(&log, "").unwrap();
+        let append = |line: &str| {
+            let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+            writeln!(file, "{}", line).unwrap();
+        };
+
+        let mut config = (*config("true")).clone();
+        config.sources.exec.as_mut().unwrap().enabled = false;
+        config.sources.file = Some(crate::config::FileSource {
+            enabled: true,
+            paths: vec![log.to_string_lossy().into_owned()],
+            service_name: None,
+            path_pattern: None,
+        });
+        config.agent.state_dir = dir.join("state");
+        let config = Arc::new(config);
+
+        let (buffer, mut rx) = Buffer::start(&config, Arc::default()).unwrap();
+        let mut supervisor = Supervisor::new(config.clone(), buffer, Arc::default());
+        supervisor.start_all();
+        // The tailer starts at the end of the file, so keep writing until it is up
+        loop {
+            append("one");
+            if let Ok(entry) = tokio::time::timeout(Duration::from_millis(100), rx.recv()).await {
+                assert_eq!(entry.unwrap().message, "one");
+                break;
+            }
+        }
+        supervisor.shutdown().await;
+        while tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().is_some() {}
+
+        // Written while the agent is down, picked up from the saved offset
+        append("two");
+        let (buffer, mut rx) = Buffer::start(&config, Arc::default()).unwrap();
+        let mut supervisor = Supervisor::new(config, buffer, Arc::default());
+        supervisor.start_all();
+        let mut message = next_message(&mut rx).await;
+        while message == "one" {
+            message = next_message(&mut rx).await;
+        }
+        assert_eq!(message, "two");
+        supervisor.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_channel() {
+        let initial = config("while true; do echo one; sleep 0.05; done");
+        let (buffer, mut rx) = Buffer::start(&initial, Arc::default()).unwrap();
+        let mut supervisor = Supervisor::new(initial, buffer, Arc::default());
+        supervisor.start_all();
+        assert_eq!(next_message(&mut rx).await, "one");
+
+        supervisor.shutdown().await;
+        // Queued entries are still delivered, then the channel closes
+        while tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().is_some() {}
+    }
+}
@@ -1,105 +1,214 @@
 use anyhow::{Context, Result};
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpStream;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Mutex};
 use tokio::time::sleep;
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, trace, warn};
 
 use crate::config::AgentConfig;
 use crate::crypto::Encryptor;
+use crate::health::Health;
+use crate::output::Target;
 use crate::protocol::Frame;
+use crate::severity::Severity;
 
-#[derive(Debug, Clone)]
+/// Most entries sent in one batch, and held while the server is unreachable
+const MAX_BATCH: usize = 50;
+
+/// Wait before retrying a full batch that could not be sent
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// One log record, following the OpenTelemetry log data model
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEntry {
+    /// When the event happened, as reported by the source
     pub timestamp: chrono::DateTime<Utc>,
-    pub level: String,
+    /// When the agent read the entry
+    #[serde(default = "Utc::now")]
+    pub observed_timestamp: chrono::DateTime<Utc>,
+    pub severity: Severity,
     pub service: String,
+    /// Body as text: what the processors match, and what is sent unless `body` is set
     pub message: String,
+    /// Body as the source sent it when it is not a string (a map, a list, a number)
+    #[serde(default)]
+    pub body: Option<serde_json::Value>,
     pub attributes: Option<serde_json::Value>,
+    #[serde(default)]
+    pub trace: Option<TraceContext>,
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    /// Resource attributes of this entry (a pod, a container), on top of the
+    /// host attributes sent once per batch
+    #[serde(default)]
+    pub resource: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+/// W3C trace context of the operation that produced an entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits
+    pub trace_id: String,
+    /// 16 lowercase hex digits
+    pub span_id: Option<String>,
+    pub trace_flags: Option<u8>,
+}
+
+/// Instrumentation scope (library or component) that emitted an entry
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scope {
+    pub name: String,
+    pub version: Option<String>,
+    pub attributes: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
+fn unix_nanos(time: &chrono::DateTime<Utc>) -> String {
+    time.timestamp_nanos_opt().unwrap_or(0).to_string()
+}
+
+impl LogEntry {
+    /// JSON form shared with the backend's `OtelLog`
+    pub fn to_otlp_json(&self) -> serde_json::Value {
+        let mut log = json!({
+            "timeUnixNano": unix_nanos(&self.timestamp),
+            "observedTimeUnixNano": unix_nanos(&self.observed_timestamp),
+            "severityText": self.severity.text(),
+            "severityNumber": self.severity.number(),
+            "serviceName": self.service,
+            "body": self.body.clone().unwrap_or_else(|| self.message.clone().into()),
+            "logAttributes": self.attributes,
+        });
+        if let Some(resource) = &self.resource {
+            log["resourceAttributes"] = resource.clone().into();
+        }
+        if let Some(scope) = &self.scope {
+            log["scopeName"] = scope.name.clone().into();
+            if let Some(version) = &scope.version {
+                log["scopeVersion"] = version.clone().into();
+            }
+            if let Some(attributes) = &scope.attributes {
+                log["scopeAttributes"] = attributes.clone().into();
+            }
+        }
+        if let Some(trace) = &self.trace {
+            log["traceId"] = trace.trace_id.clone().into();
+            if let Some(span_id) = &trace.span_id {
+                log["spanId"] = span_id.clone().into();
+            }
+            if let Some(flags) = trace.trace_flags {
+                log["traceFlags"] = flags.into();
+            }
+        }
+        log
+    }
 }
 
+/// TCP connection to one server, shared by every output that sends there
+pub type Connection = Arc<Mutex<Option<TcpStream>>>;
+
+/// Sends the entries routed to one output, encrypted with its token
 pub struct TcpLogSender {
-    config: Arc<AgentConfig>,
+    /// Output name, `default` for the `[agent]` project
+    name: String,
+    server: String,
     encryptor: Encryptor,
+    resource: serde_json::Value,
     buffer: Vec<LogEntry>,
-    stream: Option<TcpStream>,
+    connection: Connection,
 }
 
 impl TcpLogSender {
-    pub fn new(config: Arc<AgentConfig>) -> Result<Self> {
-        let encryptor = Encryptor::from_token(&config.agent.token)?;
+    pub fn new(config: &AgentConfig, target: &Target, connection: Connection) -> Result<Self> {
+        let encryptor = Encryptor::from_token(&target.token)?;
+        let resource = crate::resource::detect(&config.resource);
+        info!("Resource attributes: {}", serde_json::Value::Object(resource.clone()));
         Ok(Self {
-            config,
+            name: target.name.clone(),
+            server: target.server.clone(),
             encryptor,
+            resource: serde_json::Value::Object(resource),
             buffer: Vec::new(),
-            stream: None,
+            connection,
         })
     }
 
-    pub async fn start(
-        config: Arc<AgentConfig>,
-        mut rx: mpsc::Receiver<LogEntry>,
-    ) -> Result<()> {
-        let mut sender = Self::new(config.clone())?;
+    /// Send the entries from `rx` until it closes. With `heartbeat`, this
+    /// sender also sends its connection's heartbeat and health report.
+    pub async fn start(mut self, mut rx: mpsc::Receiver<LogEntry>, heartbeat: Option<Arc<Health>>) -> Result<()> {
         let micro_batch_delay = Duration::from_millis(10);
         let mut heartbeat_interval = tokio::time::interval(Duration::from_secs(30));
 
         loop {
             tokio::select! {
-                Some(log) = rx.recv() => {
-                    info!("Received log entry: {} - {}", log.service, log.message.chars().take(100).collect::<String>());
-                    sender.buffer.push(log);
+                // While the server is unreachable, entries wait in the buffer
+                // (where the overflow policies apply) rather than here
+                log = rx.recv(), if self.buffer.len() < MAX_BATCH => {
+                    // Every producer is gone: the agent is shutting down
+                    let Some(log) = log else {
+                        info!("Log channel closed, sending {} buffered logs to {}", self.buffer.len(), self.name);
+                        return self.flush().await;
+                    };
+                    trace!("Received log entry: {} - {}", log.service, log.message.chars().take(100).collect::<String>());
+                    self.buffer.push(log);
                     
                     tokio::time::sleep(micro_batch_delay).await;
                     
                     while let Ok(log) = rx.try_recv() {
-                        info!("Received additional log entry: {} - {}", log.service, log.message.chars().take(100).collect::<String>());
-                        sender.buffer.push(log);
-                        if sender.buffer.len() >= 50 {
+                        trace!("Received additional log entry: {} - {}", log.service, log.message.chars().take(100).collect::<String>());
+                        self.buffer.push(log);
+                        if self.buffer.len() >= MAX_BATCH {
                             break;
                         }
                     }
                     
-                    info!("Buffered {} logs, flushing...", sender.buffer.len());
+                    debug!("Buffered {} logs for {}, flushing...", self.buffer.len(), self.name);
                     
-                    if let Err(e) = sender.flush().await {
+                    if let Err(e) = self.flush().await {
+                        error!("Failed to flush logs: {}", e);
+                    }
+                }
+                _ = sleep(RETRY_DELAY), if self.buffer.len() >= MAX_BATCH => {
+                    if let Err(e) = self.flush().await {
                         error!("Failed to flush logs: {}", e);
-                        if sender.stream.is_some() {
-                            warn!("✗ Disconnected from backend server: {}", sender.config.agent.server);
-                            sender.stream = None;
-                        }
                     }
                 }
-                _ = heartbeat_interval.tick() => {
-                    info!("Sending heartbeat to server");
-                    if let Err(e) = sender.send_heartbeat().await {
+                _ = heartbeat_interval.tick(), if heartbeat.is_some() => {
+                    debug!("Sending heartbeat to {}", self.server);
+                    let health = heartbeat.as_deref().unwrap();
+                    if let Err(e) = self.send_heartbeat(health).await {
                         warn!("Failed to send heartbeat: {}", e);
-                        if sender.stream.is_some() {
-                            warn!("✗ Disconnected from backend server: {}", sender.config.agent.server);
-                            sender.stream = None;
-                        }
                     } else {
-                        info!("Heartbeat sent successfully");
+                        debug!("Heartbeat sent successfully");
                     }
                 }
             }
         }
     }
 
-    async fn ensure_connected(&mut self) -> Result<&mut TcpStream> {
-        if self.stream.is_none() {
-            info!("Connecting to {}", self.config.agent.server);
-            let stream = TcpStream::connect(&self.config.agent.server)
+    /// Write a frame on the shared connection, connecting first if needed.
+    /// A failed write drops the connection for every output using it.
+    async fn write_frame(&self, frame: &Frame) -> Result<()> {
+        let mut stream = self.connection.lock().await;
+        if stream.is_none() {
+            info!("Connecting to {}", self.server);
+            let connected = TcpStream::connect(&self.server)
                 .await
                 .context("Failed to connect to server")?;
-            stream.set_nodelay(true)?;
-            info!("✓ Connection established with backend server: {}", self.config.agent.server);
-            self.stream = Some(stream);
+            connected.set_nodelay(true)?;
+            info!("✓ Connection established with backend server: {}", self.server);
+            *stream = Some(connected);
+        }
+
+        let result = frame.write_to(stream.as_mut().unwrap()).await;
+        if result.is_err() {
+            warn!("✗ Disconnected from backend server: {}", self.server);
+            *stream = None;
         }
-        Ok(self.stream.as_mut().unwrap())
+        result
     }
 
     async fn flush(&mut self) -> Result<()> {
@@ -107,9 +216,7 @@ impl TcpLogSender {
             return Ok(());
         }
 
-        let json_payload = self.serialize_logs(&self.buffer);
-        let compressed = self.compress(&json_payload)?;
-        let encrypted = self.encryptor.encrypt(&compressed)?;
+        let encrypted = self.encode_batch(&self.buffer)?;
         let encrypted_len = encrypted.len();
 
         let frame = Frame::log_batch(encrypted);
@@ -118,31 +225,18 @@ impl TcpLogSender {
         const MAX_RETRIES: u32 = 3;
 
         loop {
-            match self.ensure_connected().await {
-                Ok(stream) => {
-                    match frame.write_to(stream).await {
-                        Ok(_) => {
-                            info!("Successfully sent {} logs ({} bytes compressed, {} bytes encrypted)",
-                                self.buffer.len(),
-                                compressed.len(),
-                                encrypted_len
-                            );
-                            self.buffer.clear();
-                            return Ok(());
-                        }
-                        Err(e) => {
-                            error!("Failed to write frame: {}", e);
-                            self.stream = None;
-                            retry_count += 1;
-                            if retry_count >= MAX_RETRIES {
-                                anyhow::bail!("Max retries exceeded");
-                            }
-                            sleep(Duration::from_secs(1 << retry_count)).await;
-                        }
-                    }
+            match self.write_frame(&frame).await {
+                Ok(()) => {
+                    debug!("Successfully sent {} logs to {} ({} bytes compressed and encrypted)",
+                        self.buffer.len(),
+                        self.name,
+                        encrypted_len
+                    );
+                    self.buffer.clear();
+                    return Ok(());
                 }
                 Err(e) => {
-                    error!("Failed to connect: {}", e);
+                    error!("Failed to send batch to {}: {:#}", self.server, e);
                     retry_count += 1;
                     if retry_count >= MAX_RETRIES {
                         anyhow::bail!("Max retries exceeded");
@@ -153,28 +247,32 @@ impl TcpLogSender {
         }
     }
 
-    async fn send_heartbeat(&mut self) -> Result<()> {
-        let stream = self.ensure_connected().await?;
-        let frame = Frame::heartbeat();
-        frame.write_to(stream).await?;
-        Ok(())
+    /// Heartbeat with the provider health report, encoded like a batch
+    async fn send_heartbeat(&mut self, health: &Health) -> Result<()> {
+        let frame = Frame::heartbeat_with(self.encode(&serde_json::to_vec(&health.snapshot())?)?);
+        self.write_frame(&frame).await
+    }
+
+    /// Batch payload as it goes on the wire: JSON, LZ4-compressed, then encrypted
+    pub fn encode_batch(&self, logs: &[LogEntry]) -> Result<Vec<u8>> {
+        self.encode(&self.serialize_logs(logs))
+    }
+
+    fn encode(&self, json_payload: &[u8]) -> Result<Vec<u8>> {
+        let compressed = self.compress(json_payload)?;
+        self.encryptor.encrypt(&compressed)
     }
 
     fn serialize_logs(&self, logs: &[LogEntry]) -> Vec<u8> {
-        let otlp_logs: Vec<serde_json::Value> = logs
-            .iter()
-            .map(|log| {
-                json!({
-                    "timeUnixNano": log.timestamp.timestamp_nanos_opt().unwrap_or(0).to_string(),
-                    "severityText": log.level.to_uppercase(),
-                    "serviceName": log.service,
-                    "body": log.message,
-                    "logAttributes": log.attributes,
-                })
-            })
-            .collect();
-
-        serde_json::to_vec(&otlp_logs).unwrap_or_default()
+        let otlp_logs: Vec<serde_json::Value> = logs.iter().map(LogEntry::to_otlp_json).collect();
+
+        // Resource attributes are shared by the whole batch, so they travel once
+        let batch = json!({
+            "resourceAttributes": self.resource,
+            "logs": otlp_logs,
+        });
+
+        serde_json::to_vec(&batch).unwrap_or_default()
     }
 
     fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
@@ -56,6 +56,15 @@ else
     echo "✓ Binary not found"
 fi
 
+echo ""
+echo "→ Removing state directory..."
+if [ -d "/var/lib/ilog-agent" ]; then
+    rm -rf "/var/lib/ilog-agent"
+    echo "✓ State directory removed"
+else
+    echo "✓ State directory not found"
+fi
+
 echo ""
 read -p "Remove config directory $CONFIG_DIR? (y/N): " -n 1 -r
 echo